@@ -1,17 +1,546 @@
-use clap::Parser;
+use std::{fs, path::PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use datagen::{
+    chains::named_chain, font_binaries::FontBinaries, fonts_xml::Familyset, slices::SliceTables,
+};
+use itemizer::{
+    Direction, ItemizeOptions, Run,
+    css2::{self, Css2Options, DEFAULT_MAX_URL_LEN, FontDisplay},
+    explain::Decision,
+    fallback_chain::FallbackChain,
+    locale::LocaleList,
+    planner::{Page, PlanOptions, SitePlan, Strategy},
+};
+use serde_json::json;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Split text into runs, each in a single family, and print the Google Fonts requests for
+    /// them or the runs themselves
+    Itemize {
+        #[command(flatten)]
+        input: Input,
+
+        /// How to print the runs
+        #[arg(short, long, value_enum, default_value_t = Format::Urls)]
+        format: Format,
+
+        /// The font-display to request, e.g. swap, used with --format urls
+        #[arg(long)]
+        display: Option<FontDisplay>,
+
+        /// Split a family's characters across several requests to keep each URL this short
+        #[arg(long, default_value_t = DEFAULT_MAX_URL_LEN)]
+        max_url_len: usize,
+
+        #[command(flatten)]
+        itemize: ItemizeArgs,
+    },
+    /// Print the family chosen for each grapheme, every family considered, and why the winner won
+    Explain {
+        #[command(flatten)]
+        input: Input,
+
+        #[command(flatten)]
+        itemize: ItemizeArgs,
+    },
+    /// Itemize every page of a site and plan Google Fonts requests for it, sharing subsets
+    /// between pages where that's cheaper than requesting just what each page needs
+    Plan {
+        /// The pages, one json object per line, e.g. {"page": "/", "text": "Hello", "lang": "en"}
+        #[arg(long)]
+        corpus: PathBuf,
+
+        #[command(flatten)]
+        chain: ChainArgs,
+
+        /// How many pages, of those using a family, a typical visit views
+        #[arg(long, default_value_t = PlanOptions::default().pages_per_visit)]
+        pages_per_visit: usize,
+
+        /// The share of the pages using a family that must use a character for a hybrid plan
+        /// to share it
+        #[arg(long, default_value_t = PlanOptions::default().shared_min_share)]
+        shared_min_share: f64,
+
+        /// The chance a visitor already has a unicode-range slice cached, slices only being
+        /// planned for chains compiled with datagen --css2-dir
+        #[arg(long, default_value_t = PlanOptions::default().slice_hit_rate)]
+        slice_hit_rate: f64,
+
+        /// The font-display to request, e.g. swap
+        #[arg(long)]
+        display: Option<FontDisplay>,
+
+        /// Split a family's characters across several requests to keep each URL this short
+        #[arg(long, default_value_t = DEFAULT_MAX_URL_LEN)]
+        max_url_len: usize,
+    },
+}
+
+/// What to itemize and what to itemize it against
+#[derive(clap::Args, Debug)]
+struct Input {
     /// The string we want to itemize
     #[arg(short, long)]
     text: String,
 
-    /// The language to prefer when breaking ties, particularly crucial for CJK due to Han unification
+    /// The languages to prefer, most preferred first, e.g. ja,ko,en
+    ///
+    /// Particularly crucial for CJK due to Han unification.
     #[arg(short, long, default_value = "")]
-    lang: String,
+    lang: LocaleList,
+
+    #[command(flatten)]
+    chain: ChainArgs,
+}
+
+/// How to itemize
+#[derive(clap::Args, Debug)]
+struct ItemizeArgs {
+    /// Also split runs by Unicode script, as a shaper wants them
+    #[arg(long)]
+    scripts: bool,
+
+    /// Also split runs by bidi embedding level, as a shaper wants them
+    #[arg(long)]
+    bidi: bool,
+
+    /// Keep spaces, punctuation and digits in the family of the text around them
+    #[arg(long)]
+    sticky_neutrals: bool,
+
+    /// Keep each word in one family if any family supports all of it
+    #[arg(long)]
+    stable_words: bool,
+
+    /// Use as few families as possible, each one being another request
+    #[arg(long)]
+    minimize_families: bool,
+
+    /// Use the families that minimize the estimated download
+    #[arg(long)]
+    minimize_download: bool,
+
+    /// Keep as much as possible in one family and report how much that is, e.g. for a heading
+    #[arg(long)]
+    headline: bool,
+}
+
+impl ItemizeArgs {
+    fn options(&self) -> ItemizeOptions {
+        ItemizeOptions {
+            script_runs: self.scripts,
+            bidi: self.bidi,
+            sticky_neutrals: self.sticky_neutrals,
+            stable_words: self.stable_words,
+            minimize_families: self.minimize_families,
+            minimize_download: self.minimize_download,
+            headline: self.headline,
+        }
+    }
+}
+
+/// The fallback chain to itemize against
+#[derive(clap::Args, Debug)]
+struct ChainArgs {
+    #[command(flatten)]
+    source: ChainSource,
+
+    /// The fonts.xml family to put at the head of the fallback chain, used with --fonts-dir
+    #[arg(long, default_value = "sans-serif")]
+    head: String,
+}
+
+/// Where to get the fallback chain from
+#[derive(clap::Args, Debug)]
+#[group(required = true, multiple = false)]
+struct ChainSource {
+    /// A directory to search for font binaries, e.g. a clone of https://github.com/google/fonts
+    #[arg(long)]
+    fonts_dir: Option<PathBuf>,
+
+    /// A chain compiled by datagen
+    #[arg(long)]
+    chain: Option<PathBuf>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// Google Fonts css2 requests for the text of each family, one URL per line
+    Urls,
+    /// Human readable, aligned columns
+    Table,
+    /// One json object per run
+    Jsonl,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+fn load_chain(args: &ChainArgs) -> Result<FallbackChain, String> {
+    if let Some(chain) = args.source.chain.as_ref() {
+        return FallbackChain::load(chain)
+            .map_err(|e| format!("Unable to load chain from {chain:?}: {e}"));
+    }
+    let fonts_dir = args.source.fonts_dir.as_ref().unwrap();
+    let Some(fonts_dir) = fonts_dir.to_str() else {
+        return Err(format!("Non-unicode fonts dir {fonts_dir:?}"));
+    };
+    let familyset = Familyset::fonts_xml_for_googlefonts();
+    let font_binaries = FontBinaries::from_dir(fonts_dir);
+    named_chain(
+        &familyset,
+        &font_binaries,
+        &SliceTables::default(),
+        &args.head,
+    )
+    .map_err(|e| format!("Unable to build {} chain from {fonts_dir}: {e}", args.head))
+}
+
+/// Report an error we can't go on from and exit
+fn exit_with(msg: &str) -> ! {
+    eprintln!("{msg}");
+    std::process::exit(1);
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// How a run without a family appears in the table
+const MISSING: &str = "(missing)";
+
+/// The slices a run touches, space separated, empty if its family has no slices
+fn slices(text: &str, run: &Run) -> String {
+    run.slices(text)
+        .unwrap_or_default()
+        .iter()
+        .map(|idx| idx.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn print_runs(text: &str, runs: &[Run], format: Format, css2: &Css2Options) {
+    match format {
+        Format::Urls => {
+            for url in css2::urls(text, runs, css2) {
+                println!("{url}");
+            }
+        }
+        Format::Table => {
+            let family_width = runs
+                .iter()
+                .map(|r| {
+                    r.family
+                        .map(|f| f.family_name.as_str())
+                        .unwrap_or(MISSING)
+                        .len()
+                })
+                .chain(Some("family".len()))
+                .max()
+                .unwrap_or_default();
+            let slices_width = runs
+                .iter()
+                .map(|r| slices(text, r).len())
+                .chain(Some("slices".len()))
+                .max()
+                .unwrap_or_default();
+            println!(
+                "{:>5} {:>5} {:family_width$} {:6} {:5} {:slices_width$} text",
+                "start", "end", "family", "script", "level", "slices"
+            );
+            for run in runs {
+                println!(
+                    "{:>5} {:>5} {:family_width$} {:6} {:5} {:slices_width$} {:?}",
+                    run.start,
+                    run.end,
+                    run.family
+                        .map(|f| f.family_name.as_str())
+                        .unwrap_or(MISSING),
+                    run.script.as_ref().map(|s| s.as_str()).unwrap_or_default(),
+                    run.level.map(|l| l.to_string()).unwrap_or_default(),
+                    slices(text, run),
+                    &text[run.start..run.end]
+                );
+            }
+        }
+        Format::Jsonl => {
+            for run in runs {
+                println!(
+                    "{}",
+                    json!({
+                        "start": run.start,
+                        "end": run.end,
+                        "text": &text[run.start..run.end],
+                        "family": run.family.map(|f| f.family_name.as_str()),
+                        "script": run.script.as_ref().map(|s| s.as_str()),
+                        "level": run.level,
+                        "direction": run.direction.map(|d| match d {
+                            Direction::Ltr => "ltr",
+                            Direction::Rtl => "rtl",
+                        }),
+                        "slices": run.slices(text),
+                    })
+                );
+            }
+        }
+        Format::Csv => {
+            println!("start,end,family,script,level,slices,text");
+            for run in runs {
+                println!(
+                    "{},{},{},{},{},{},{}",
+                    run.start,
+                    run.end,
+                    csv_field(
+                        run.family
+                            .map(|f| f.family_name.as_str())
+                            .unwrap_or_default()
+                    ),
+                    run.script.as_ref().map(|s| s.as_str()).unwrap_or_default(),
+                    run.level.map(|l| l.to_string()).unwrap_or_default(),
+                    slices(text, run),
+                    csv_field(&text[run.start..run.end])
+                );
+            }
+        }
+    }
+}
+
+/// Read pages from json lines, each {"page", "text", "lang"}, lang being optional
+fn load_corpus(path: &PathBuf) -> Result<Vec<Page>, String> {
+    let raw = fs::read_to_string(path).map_err(|e| format!("Unable to read {path:?}: {e}"))?;
+    let mut pages = Vec::new();
+    for (i, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at = || format!("{path:?} line {}", i + 1);
+        let value: serde_json::Value =
+            serde_json::from_str(line).map_err(|e| format!("{}: {e}", at()))?;
+        let field = |name: &str| value.get(name).and_then(|v| v.as_str());
+        let (Some(name), Some(text)) = (field("page"), field("text")) else {
+            return Err(format!("{}: page and text are required", at()));
+        };
+        let lang = LocaleList::parse(field("lang").unwrap_or_default())
+            .map_err(|e| format!("{}: {e}", at()))?;
+        pages.push(Page {
+            name: name.to_string(),
+            text: text.to_string(),
+            lang,
+        });
+    }
+    Ok(pages)
+}
+
+const STRATEGIES: [Strategy; 4] = [
+    Strategy::SiteWide,
+    Strategy::Hybrid,
+    Strategy::PageSpecific,
+    Strategy::Slices,
+];
+
+/// "site-wide 600, hybrid 500, page-specific 900", leaving out strategies without bytes
+fn by_strategy(bytes: impl Fn(Strategy) -> Option<u64>) -> String {
+    STRATEGIES
+        .into_iter()
+        .filter_map(|strategy| Some(format!("{} {}", strategy.as_str(), bytes(strategy)?)))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn print_plan(pages: &[Page], plan: &SitePlan, css2: &Css2Options) {
+    for family in plan.families.iter() {
+        println!(
+            "{} {}, bytes a visit: {}",
+            family.family.family_name,
+            family.strategy.as_str(),
+            by_strategy(|strategy| family.estimate(strategy).map(|e| e.per_visit))
+        );
+        for url in family.shared_urls(css2) {
+            println!("  {url}");
+        }
+    }
+    for (idx, page) in pages.iter().enumerate() {
+        // A strategy some family the page uses can't follow has no total
+        let bytes = |strategy, warm: bool| {
+            plan.families
+                .iter()
+                .filter(|f| f.used[idx])
+                .map(|f| {
+                    let estimate = f.estimate(strategy)?;
+                    Some(match warm {
+                        true => estimate.warm[idx],
+                        false => estimate.cold[idx],
+                    })
+                })
+                .sum::<Option<u64>>()
+        };
+        println!(
+            "{} cold bytes: {}; warm bytes: {}",
+            page.name,
+            by_strategy(|strategy| bytes(strategy, false)),
+            by_strategy(|strategy| bytes(strategy, true))
+        );
+        for url in plan.page_urls(idx, css2) {
+            println!("  {url}");
+        }
+    }
+}
+
+fn print_decisions(text: &str, decisions: &[Decision]) {
+    for decision in decisions {
+        println!(
+            "{}..{} {:?} {} => {} ({})",
+            decision.start,
+            decision.end,
+            &text[decision.start..decision.end],
+            decision.path,
+            decision
+                .family
+                .map(|f| f.family_name.as_str())
+                .unwrap_or("nothing"),
+            decision.reason
+        );
+        for candidate in decision.candidates.iter() {
+            let winner = decision
+                .family
+                .is_some_and(|f| std::ptr::eq(f, candidate.family));
+            println!(
+                "  {} {} ({})",
+                if winner { "*" } else { " " },
+                candidate.family.family_name,
+                candidate.score
+            );
+        }
+    }
 }
 
 fn main() {
-    let _args = Args::parse();
+    // Library diagnostics go to stderr, e.g. RUST_LOG=itemizer=trace to see every grapheme
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+
+    let args = Args::parse();
+    match args.command {
+        Command::Itemize {
+            input,
+            format,
+            display,
+            max_url_len,
+            itemize,
+        } => {
+            let chain = load_chain(&input.chain).unwrap_or_else(|e| exit_with(&e));
+            let options = itemize.options();
+            let mut runs = Vec::new();
+            let mut summary = None;
+            let itemized = if itemize.headline {
+                chain
+                    .itemize_headline(&input.text, &input.lang, &options, &mut runs)
+                    .map(|headline| {
+                        summary = Some(format!(
+                            "{} covers {} of {} graphemes ({:.0}%)",
+                            headline
+                                .family
+                                .map(|f| f.family_name.as_str())
+                                .unwrap_or(MISSING),
+                            headline.covered,
+                            headline.total,
+                            headline.share() * 100.0
+                        ));
+                        headline.missing
+                    })
+            } else {
+                chain.itemize_with_options(&input.text, &input.lang, &options, &mut runs)
+            };
+            let missing = itemized
+                .unwrap_or_else(|e| exit_with(&format!("Unable to itemize {:?}: {e}", input.text)));
+            let css2 = Css2Options {
+                display,
+                max_url_len,
+            };
+            print_runs(&input.text, &runs, format, &css2);
+            if let Some(summary) = summary {
+                eprintln!("{summary}");
+            }
+            if !missing.flags.is_empty() {
+                eprintln!(
+                    "No family draws the flags for {}",
+                    missing
+                        .flags
+                        .iter()
+                        .map(|f| f.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+            if missing.graphemes > 0 {
+                eprintln!(
+                    "{} graphemes have no family, codepoints {}",
+                    missing.graphemes,
+                    missing
+                        .codepoints
+                        .iter()
+                        .map(|cp| format!("U+{cp:04X}"))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+        }
+        Command::Explain { input, itemize } => {
+            let chain = load_chain(&input.chain).unwrap_or_else(|e| exit_with(&e));
+            let decisions = chain
+                .explain_with_options(&input.text, &input.lang, &itemize.options())
+                .unwrap_or_else(|e| exit_with(&format!("Unable to explain {:?}: {e}", input.text)));
+            print_decisions(&input.text, &decisions);
+        }
+        Command::Plan {
+            corpus,
+            chain,
+            pages_per_visit,
+            shared_min_share,
+            slice_hit_rate,
+            display,
+            max_url_len,
+        } => {
+            let pages = load_corpus(&corpus).unwrap_or_else(|e| exit_with(&e));
+            let chain = load_chain(&chain).unwrap_or_else(|e| exit_with(&e));
+            let options = PlanOptions {
+                pages_per_visit,
+                shared_min_share,
+                slice_hit_rate,
+                ..Default::default()
+            };
+            let plan = chain
+                .plan(&pages, &options)
+                .unwrap_or_else(|e| exit_with(&format!("Unable to plan {corpus:?}: {e}")));
+            let css2 = Css2Options {
+                display,
+                max_url_len,
+            };
+            print_plan(&pages, &plan, &css2);
+            for (page, missing) in pages.iter().zip(plan.missing.iter()) {
+                if missing.graphemes > 0 {
+                    eprintln!(
+                        "{} has {} graphemes no family draws",
+                        page.name, missing.graphemes
+                    );
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,127 @@
+//! Builds [FallbackChain]s from fonts.xml and font binaries
+
+use std::iter::once;
+
+use itemizer::{
+    Error,
+    fallback_chain::{self, FallbackChain},
+};
+
+use crate::{
+    font_binaries::{FamilyName, Filename, FontBinaries},
+    fonts_xml::{Familyset, Font, Style, Variant},
+    slices::SliceTables,
+};
+
+/// Builds the fallback chain for the named family (e.g. sans-serif) followed by all fallbacks
+///
+/// Fallbacks whose font binary can't be located are left out of the chain, the head's being
+/// missing is an error. Families in slices get their unicode-range slices.
+pub fn named_chain(
+    familyset: &Familyset,
+    font_binaries: &FontBinaries,
+    slices: &SliceTables,
+    head: &str,
+) -> Result<FallbackChain, Error> {
+    let unwantedness = |font: &Font| {
+        // Having the specified fallback name is best
+        // Then no fallback name
+        // Worst of all, the wrong fallback name
+        let mut score = match font.fallback_for.as_deref() {
+            Some(v) if v == head => 0,
+            None => 10000,
+            Some(_) => 100000,
+        };
+        if font.style == Style::Italic {
+            score += 1000;
+        }
+
+        // Prefer nearest 400, failing that higher is better
+        score += match font.weight - 400.0 {
+            0.0 => 0,
+            v if v > 0.0 => v as u32,
+            v => (v + 50.0) as u32,
+        };
+
+        score
+    };
+
+    let Some(sans) = familyset.named(head) else {
+        return Err(Error::UnknownFamily(head.into()));
+    };
+    let mut families = once(sans)
+        .chain(familyset.fallbacks())
+        .map(|family| {
+            // Pick the best font from each family
+            let font = family
+                .fonts
+                .iter()
+                .reduce(|acc, e| {
+                    if unwantedness(acc) <= unwantedness(e) {
+                        acc
+                    } else {
+                        e
+                    }
+                })
+                .unwrap_or_else(|| panic!("No family should be fontless! {family:?}"));
+            let filename = Filename((&font.filename).into());
+            let family_name: FamilyName = (&filename).into();
+            fallback_chain::Family {
+                lang: family.lang.as_deref().map(|s| s.into()),
+                variant: match family.variant {
+                    Variant::Default => fallback_chain::Variant::Default,
+                    Variant::Compact => fallback_chain::Variant::Compact,
+                    Variant::Elegant => fallback_chain::Variant::Elegant,
+                },
+                ..fallback_chain::Family::new(family_name.0)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let located = |family: &fallback_chain::Family| {
+        font_binaries
+            .filename(&FamilyName(family.family_name.clone()))
+            .and_then(|filename| font_binaries.local_file(filename))
+            .is_some_and(|path| path.is_file())
+    };
+    // Without the head every codepoint of the first fallback would count as the head's
+    if !located(&families[0]) {
+        return Err(Error::NoCoverage(families[0].family_name.clone()));
+    }
+    families.retain(|family| {
+        let located = located(family);
+        if !located {
+            eprintln!("No font binary for {}, omitting it", family.family_name);
+        }
+        located
+    });
+
+    let fonts = families
+        .into_iter()
+        .map(|mut family| {
+            let filename = font_binaries.filename(&FamilyName(family.family_name.clone()));
+            family.emoji = filename
+                .and_then(|filename| font_binaries.is_color(filename))
+                .unwrap_or_default();
+            // Emoji fonts ligate sequences, for anything else composing codepoints is fine
+            if family.emoji {
+                family.sequences = filename.and_then(|filename| font_binaries.sequences(filename));
+            }
+            // Google Fonts serves subsets so what matters is the cost of each codepoint
+            family.cost = filename
+                .and_then(|filename| font_binaries.bytes_per_codepoint(filename))
+                .map(fallback_chain::Cost::PerCodepoint);
+            family.slices = slices
+                .slices(&FamilyName(family.family_name.clone()))
+                .cloned();
+            family
+        })
+        .collect::<Vec<_>>();
+
+    FallbackChain::for_fonts(head, fonts, |font| {
+        font_binaries
+            .filename(&FamilyName(font.family_name.clone()))
+            .and_then(|filename| font_binaries.codepoints(filename))
+            .unwrap_or_default()
+    })
+}
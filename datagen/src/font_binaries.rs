@@ -1,16 +1,21 @@
 //! Access to font files
 
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeSet, HashMap, HashSet, hash_map::Entry},
     fmt::Display,
     fs::{self, File},
+    iter::once,
     path::{Path, PathBuf},
 };
 
+use itertools::Itertools;
 use memmap2::Mmap;
 use regex::Regex;
 use reqwest::blocking::Client as BlockingClient;
-use skrifa::{FontRef, MetadataProvider};
+use skrifa::{
+    FontRef, MetadataProvider, Tag,
+    raw::{TableProvider, tables::gsub::SubstitutionSubtables},
+};
 use smol_str::SmolStr;
 use walkdir::WalkDir;
 
@@ -34,6 +39,19 @@ impl Display for FamilyName {
     }
 }
 
+impl FamilyName {
+    /// Key for matching family names regardless of case or spacing
+    ///
+    /// Files on disk, e.g. in a clone of google/fonts, don't use the same case as fonts.xml
+    pub(crate) fn lookup_key(&self) -> SmolStr {
+        self.0
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    }
+}
+
 impl From<&Filename> for FamilyName {
     fn from(filename: &Filename) -> Self {
         let filename = filename.0.as_str();
@@ -41,6 +59,9 @@ impl From<&Filename> for FamilyName {
         // Drop ext
         let filename = &filename[0..filename.len() - 4];
 
+        // Something[wght] => Something
+        let filename = filename.split_once('[').map(|p| p.0).unwrap_or(filename);
+
         // Something-Regular => Something or Something-VF => Something
         let filename = filename.split_once('-').map(|p| p.0).unwrap_or(filename);
 
@@ -53,12 +74,59 @@ impl From<&Filename> for FamilyName {
 /// Makes font binaries available for data generation
 pub struct FontBinaries {
     local_files: HashMap<Filename, PathBuf>,
-    by_family_name: HashMap<FamilyName, Filename>,
+    by_family_name: HashMap<SmolStr, Filename>,
+}
+
+fn is_variation_selector(cp: u32) -> bool {
+    matches!(cp, 0xfe00..=0xfe0f | 0xe0100..=0xe01ef)
+}
+
+/// ZWJ, variation selectors and tags, the invisible parts of emoji sequences
+fn is_sequence_joiner(cp: u32) -> bool {
+    cp == 0x200d || is_variation_selector(cp) || matches!(cp, 0xe0020..=0xe007f)
+}
+
+/// What a ligature component drawn with a glyph mapped from cps can be, None being a variation
+/// selector, which sequences leave out
+///
+/// Fonts often share one empty glyph among default ignorables, e.g. ZWSP, ZWJ and FE0F, so a
+/// glyph mapped from any of ZWJ, a variation selector or a tag can be any of them. Otherwise
+/// the lowest codepoint will do.
+fn component_readings(cps: &[u32]) -> Vec<Option<u32>> {
+    let mut readings = cps
+        .iter()
+        .filter(|cp| is_sequence_joiner(**cp))
+        .map(|cp| Some(*cp).filter(|cp| !is_variation_selector(*cp)))
+        .collect::<Vec<_>>();
+    if readings.is_empty() {
+        readings.extend(cps.iter().min().map(|cp| Some(*cp)));
+    }
+    readings.sort();
+    readings.dedup();
+    readings
+}
+
+fn is_italic(filename: &Filename) -> bool {
+    filename.0.to_ascii_lowercase().contains("italic")
 }
 
 impl FontBinaries {
     fn new(local_files: HashMap<Filename, PathBuf>) -> Self {
-        let by_family_name = local_files.keys().map(|f| (f.into(), f.clone())).collect();
+        let mut by_family_name = HashMap::new();
+        for filename in local_files.keys() {
+            let key = FamilyName::from(filename).lookup_key();
+            match by_family_name.entry(key) {
+                Entry::Vacant(e) => {
+                    e.insert(filename.clone());
+                }
+                // Something[wght].ttf and Something-Italic[wght].ttf are the same family, prefer upright
+                Entry::Occupied(mut e) => {
+                    if is_italic(e.get()) && !is_italic(filename) {
+                        e.insert(filename.clone());
+                    }
+                }
+            }
+        }
         Self {
             local_files,
             by_family_name,
@@ -68,11 +136,10 @@ impl FontBinaries {
     pub fn from_web(local_cache: &Path, families: &[&Family]) -> Self {
         let local_files = families
             .iter()
-            .map(|family| {
+            .inspect(|family| {
                 for font in family.fonts.iter() {
                     debug_assert!(!font.filename.contains("CJK"), "{family:#?}");
                 }
-                family
             })
             .flat_map(|f| f.fonts.iter().map(|f| f.filename.as_str()))
             .map(|filename| {
@@ -134,7 +201,7 @@ impl FontBinaries {
             };
 
             eprintln!("Writing {} bytes to {:?}", bytes.len(), local_file);
-            fs::write(&local_file, &*bytes)
+            fs::write(local_file, &*bytes)
                 .unwrap_or_else(|e| panic!("Unable to write to {local_file:?} {e:?}"));
         }
 
@@ -166,7 +233,7 @@ impl FontBinaries {
                 eprintln!("Multiple files named {:?} :(", e.file_name());
             }
         }
-        println!("{} font files", local_files.len());
+        eprintln!("{} font files", local_files.len());
         Self::new(local_files)
     }
 
@@ -175,27 +242,100 @@ impl FontBinaries {
     }
 
     pub fn filename(&self, family_name: &FamilyName) -> Option<&Filename> {
-        self.by_family_name.get(family_name)
+        self.by_family_name.get(&family_name.lookup_key())
     }
 
-    pub fn codepoints(&self, filename: &Filename) -> Option<HashSet<u32>> {
-        let Some(path) = self.local_file(filename) else {
-            return None;
-        };
+    fn with_font<T>(&self, filename: &Filename, f: impl FnOnce(&FontRef) -> T) -> Option<T> {
+        let path = self.local_file(filename)?;
         if !path.is_file() {
             return None;
         }
-        let file = File::open(&path).unwrap_or_else(|e| panic!("Unable to read {path:?}: {e}"));
+        let file = File::open(path).unwrap_or_else(|e| panic!("Unable to read {path:?}: {e}"));
         let mmap =
             unsafe { Mmap::map(&file).unwrap_or_else(|e| panic!("Unable to mmap {path:?}: {e}")) };
-        let font = FontRef::new(&*mmap)
+        let font = FontRef::new(&mmap)
             .unwrap_or_else(|e| panic!("Unable to create a fontref for {path:?}: {e}"));
+        Some(f(&font))
+    }
+
+    pub fn codepoints(&self, filename: &Filename) -> Option<HashSet<u32>> {
+        self.with_font(filename, |font| {
+            font.charmap().mappings().map(|(cp, _)| cp).collect()
+        })
+    }
+
+    /// The sequences of codepoints the font ligates into a single glyph, per its GSUB
+    ///
+    /// Variation selectors are dropped, as [itemizer::fallback_chain::Family::sequences] expects.
+    /// Ligatures with a component that isn't directly mapped from a codepoint are skipped. A
+    /// ligature with a glyph that could be any of several joiners is kept for every reading,
+    /// see [component_readings], those no text has being harmless.
+    pub fn sequences(&self, filename: &Filename) -> Option<BTreeSet<Vec<u32>>> {
+        self.with_font(filename, |font| {
+            let mut cps_for_gid = HashMap::<u32, Vec<u32>>::new();
+            for (cp, gid) in font.charmap().mappings() {
+                cps_for_gid.entry(gid.to_u32()).or_default().push(cp);
+            }
+            let mut sequences = BTreeSet::new();
+            let Ok(lookups) = font.gsub().and_then(|gsub| gsub.lookup_list()) else {
+                return sequences;
+            };
+            for lookup in lookups.lookups().iter().flatten() {
+                let Ok(SubstitutionSubtables::Ligature(subtables)) = lookup.subtables() else {
+                    continue;
+                };
+                for subtable in subtables.iter().flatten() {
+                    let Ok(coverage) = subtable.coverage() else {
+                        continue;
+                    };
+                    for (first, set) in coverage.iter().zip(subtable.ligature_sets().iter()) {
+                        let Ok(set) = set else {
+                            continue;
+                        };
+                        for ligature in set.ligatures().iter().flatten() {
+                            let Some(components) = once(first)
+                                .chain(ligature.component_glyph_ids().iter().map(|g| g.get()))
+                                .map(|gid| {
+                                    cps_for_gid
+                                        .get(&gid.to_u32())
+                                        .map(|cps| component_readings(cps))
+                                })
+                                .collect::<Option<Vec<_>>>()
+                            else {
+                                continue;
+                            };
+                            for reading in components.into_iter().multi_cartesian_product() {
+                                let sequence = reading.into_iter().flatten().collect::<Vec<_>>();
+                                if sequence.len() > 1 {
+                                    sequences.insert(sequence);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            sequences
+        })
+    }
+
+    /// Roughly what each codepoint of a subset of the font costs to download, the size of the
+    /// file spread evenly over the codepoints it maps
+    pub fn bytes_per_codepoint(&self, filename: &Filename) -> Option<u32> {
+        let size = fs::metadata(self.local_file(filename)?).ok()?.len();
+        let codepoints = self.with_font(filename, |font| font.charmap().mappings().count())?;
         Some(
-            font.charmap()
-                .mappings()
-                .into_iter()
-                .map(|(cp, _)| cp)
-                .collect(),
+            (size / codepoints.max(1) as u64)
+                .try_into()
+                .unwrap_or(u32::MAX),
         )
     }
+
+    /// True if the font has color glyphs, in any of the formats color emoji fonts use
+    pub fn is_color(&self, filename: &Filename) -> Option<bool> {
+        self.with_font(filename, |font| {
+            [b"COLR", b"CBDT", b"sbix", b"SVG "]
+                .into_iter()
+                .any(|tag| font.table_data(Tag::new(tag)).is_some())
+        })
+    }
 }
@@ -10,6 +10,8 @@ pub struct Familyset(pub(crate) Vec<Entry>);
 
 #[derive(Debug, Clone)]
 pub(crate) enum Entry {
+    // Read to mirror fonts.xml, nothing resolves aliases yet
+    #[allow(dead_code)]
     Alias(Alias),
     Family(Family),
 }
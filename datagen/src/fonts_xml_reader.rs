@@ -172,13 +172,13 @@ pub(crate) fn bundled_fonts_xml() -> Familyset {
                 }
             }
             Ok(Event::End(e)) => {
-                if e.name().0 == b"font" {
-                    if let Some(font) = font_in_progress.take() {
-                        let Some(Entry::Family(family)) = entries.last_mut() else {
-                            panic!("Bad bookkeeping");
-                        };
-                        family.fonts.push(font);
-                    }
+                if e.name().0 == b"font"
+                    && let Some(font) = font_in_progress.take()
+                {
+                    let Some(Entry::Family(family)) = entries.last_mut() else {
+                        panic!("Bad bookkeeping");
+                    };
+                    family.fonts.push(font);
                 }
             }
 
@@ -186,7 +186,7 @@ pub(crate) fn bundled_fonts_xml() -> Familyset {
         }
     }
 
-    println!("{} fonts.xml entries", entries.len());
+    eprintln!("{} fonts.xml entries", entries.len());
 
     Familyset(entries)
 }
@@ -1,3 +1,5 @@
+pub mod chains;
 pub mod font_binaries;
 pub mod fonts_xml;
 pub(crate) mod fonts_xml_reader;
+pub mod slices;
@@ -1,117 +1,124 @@
 //! Generates data for itemizer
-use std::{fs, iter::once, path::Path};
+use std::{fs, path::PathBuf};
 
+use clap::Parser;
 use datagen::{
-    font_binaries::{FamilyName, Filename, FontBinaries},
-    fonts_xml::{Familyset, Font, Style},
+    chains::named_chain,
+    font_binaries::{Filename, FontBinaries},
+    fonts_xml::Familyset,
+    slices::SliceTables,
 };
-use itemizer::fallback_chain::FallbackChain;
 
-fn named_chain(familyset: &Familyset, font_binaries: &FontBinaries, head: &str) -> FallbackChain {
-    let unwantedness = |font: &Font| {
-        // Having the specified fallback name is best
-        // Then no fallback name
-        // Worst of all, the wrong fallback name
-        let mut score = match font.fallback_for.as_deref() {
-            Some(v) if v == head => 0,
-            None => 10000,
-            Some(_) => 100000,
-        };
-        if font.style == Style::Italic {
-            score += 1000;
-        }
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Where to write the compiled fallback chain
+    #[arg(short, long, default_value = "sans-serif.chain")]
+    out: PathBuf,
 
-        // Prefer nearest 400, failing that higher is better
-        score += match font.weight - 400.0 {
-            0.0 => 0,
-            v if v > 0.0 => v as u32,
-            v => (v + 50.0) as u32,
-        };
+    /// Optionally also write the chain as json, e.g. for inspection
+    #[arg(long)]
+    json: Option<PathBuf>,
 
-        score
-    };
+    /// A directory to search for font binaries, e.g. a clone of https://github.com/google/fonts
+    ///
+    /// If not set fonts are downloaded from Google Fonts into local_cache
+    #[arg(long)]
+    fonts_dir: Option<String>,
 
-    let Some(sans) = familyset.named(head) else {
-        panic!("Unable to locate {head}");
-    };
-    let fonts = once(sans)
-        .chain(familyset.fallbacks())
-        .map(|family| {
-            // Pick the best font from each family
-            let font = family
-                .fonts
-                .iter()
-                .reduce(|acc, e| {
-                    if unwantedness(acc) <= unwantedness(e) {
-                        acc
-                    } else {
-                        e
-                    }
-                })
-                .unwrap_or_else(|| panic!("No family should be fontless! {family:?}"));
-            let filename = Filename((&font.filename).into());
-            let family_name: FamilyName = (&filename).into();
-            itemizer::fallback_chain::Family {
-                family_name: family_name.0,
-                lang: family.lang.as_deref().map(|s| s.into()),
-                codepoints: Default::default(),
-            }
-        })
-        .collect::<Vec<_>>();
+    /// Where to put fonts downloaded from Google Fonts
+    #[arg(long, default_value = "/tmp/local_fonts")]
+    local_cache: PathBuf,
+
+    /// The fonts.xml family to put at the head of the fallback chain
+    #[arg(long, default_value = "sans-serif")]
+    head: String,
 
-    FallbackChain::for_fonts(head, fonts, |font| {
-        font_binaries
-            .filename(&FamilyName(font.family_name.clone()))
-            .and_then(|filename| font_binaries.codepoints(filename))
-            .unwrap_or_default()
-    })
+    /// A directory of saved css2 responses, e.g. from
+    /// https://fonts.googleapis.com/css2?family=Noto+Sans+JP, to read the unicode-range slices
+    /// of each family from
+    #[arg(long)]
+    css2_dir: Option<String>,
 }
 
 fn main() {
-    let local_cache = Path::new("/tmp/local_fonts");
-    if !local_cache.is_dir() {
-        fs::create_dir(local_cache).expect("To create local fonts dir");
-    }
+    let args = Args::parse();
+
     let familyset = Familyset::fonts_xml_for_googlefonts();
-    let fallbacks = familyset.fallbacks();
-    let fonts = FontBinaries::from_web(local_cache, &fallbacks);
+    let fonts = if let Some(fonts_dir) = args.fonts_dir.as_deref() {
+        FontBinaries::from_dir(fonts_dir)
+    } else {
+        let local_cache = args.local_cache.as_path();
+        if !local_cache.is_dir() {
+            fs::create_dir(local_cache).expect("To create local fonts dir");
+        }
+        let fallbacks = familyset.fallbacks();
+        let fonts = FontBinaries::from_web(local_cache, &fallbacks);
 
-    let mut contains = 0;
-    let mut missing = 0;
-    for fallback in fallbacks {
-        for font in fallback.fonts.iter() {
-            let Some(local_file) = fonts.local_file(&Filename(font.filename.as_str().into()))
-            else {
-                missing += 1;
-                eprintln!("No entry for {}", font.filename);
-                continue;
-            };
-            if local_file.is_file() {
-                contains += 1;
-            } else {
-                missing += 1;
-                eprintln!("{} local {:?} not found", font.filename, local_file);
+        let mut contains = 0;
+        let mut missing = 0;
+        for fallback in fallbacks {
+            for font in fallback.fonts.iter() {
+                let Some(local_file) = fonts.local_file(&Filename(font.filename.as_str().into()))
+                else {
+                    missing += 1;
+                    eprintln!("No entry for {}", font.filename);
+                    continue;
+                };
+                if local_file.is_file() {
+                    contains += 1;
+                } else {
+                    missing += 1;
+                    eprintln!("{} local {:?} not found", font.filename, local_file);
+                }
             }
         }
-    }
 
-    println!("{contains}/{} fallback fonts located", contains + missing);
+        println!("{contains}/{} fallback fonts located", contains + missing);
+        fonts
+    };
 
-    let sans_chain = named_chain(&familyset, &fonts, "sans-serif");
+    let slices = args
+        .css2_dir
+        .as_deref()
+        .map(SliceTables::from_dir)
+        .unwrap_or_default();
+    let chain = named_chain(&familyset, &fonts, &slices, &args.head)
+        .unwrap_or_else(|e| panic!("Unable to build {} chain: {e}", args.head));
+    let stats = chain.stats();
+    let pct = |n: usize| 100.0 * n as f32 / stats.codepoints as f32;
+    println!(
+        "{}/{} ({:.1}%) codepoints map to exactly 1 family",
+        stats.distinct,
+        stats.codepoints,
+        pct(stats.distinct)
+    );
+    println!(
+        "{}/{} ({:.1}%) codepoints map to multiple families but one is the clear winner",
+        stats.unambiguous_conflict,
+        stats.codepoints,
+        pct(stats.unambiguous_conflict)
+    );
+    println!(
+        "{} distinct groups of fonts with ambiguous codepoints",
+        stats.conflict_groups
+    );
+    println!("{} mappings for unambiguous codepoints", stats.mappings);
+    println!(
+        "{} mappings for ambiguous codepoints across all langs",
+        stats.lang_mappings
+    );
 
-    let text = "Hello 世界 ❤️‍🔥";
-    for lang in vec!["und-Latn", "ja"] {
-        let mut dest = Vec::new();
-        sans_chain.itemize(text, lang, &mut dest).unwrap();
-        eprintln!("Runs for {text}, lang={lang}");
-        for run in dest {
-            eprintln!(
-                "({}..{}) \"{}\" in {}",
-                run.start,
-                run.end,
-                &text[run.start..run.end],
-                run.family.family_name
-            );
-        }
+    chain
+        .save(&args.out)
+        .unwrap_or_else(|e| panic!("Unable to write {:?}: {e}", args.out));
+    println!("Wrote {:?}", args.out);
+
+    if let Some(json) = args.json.as_ref() {
+        let file =
+            fs::File::create(json).unwrap_or_else(|e| panic!("Unable to create {json:?}: {e}"));
+        serde_json::to_writer(file, &chain)
+            .unwrap_or_else(|e| panic!("Unable to write {json:?}: {e}"));
+        println!("Wrote {json:?}");
     }
 }
@@ -0,0 +1,158 @@
+//! The unicode-range slices Google Fonts serves families as, read from saved css2 responses
+//!
+//! Google Fonts only slices for browsers that support woff2 and unicode-range, so save responses
+//! with a modern user agent, e.g.
+//! `curl -A "Mozilla/5.0 Chrome/120" "https://fonts.googleapis.com/css2?family=Noto+Sans+JP"`
+
+use std::{collections::HashMap, fs};
+
+use itemizer::fallback_chain::Slice;
+use smol_str::SmolStr;
+use walkdir::WalkDir;
+
+use crate::font_binaries::FamilyName;
+
+/// What an @font-face without a unicode-range covers
+const ALL_CODEPOINTS: (u32, u32) = (0, 0x10ffff);
+
+/// Parse a unicode-range token, e.g. U+0-7F, U+4?? or U+25EE8
+fn parse_range(token: &str) -> Result<(u32, u32), String> {
+    let Some(hex) = token
+        .strip_prefix("U+")
+        .or_else(|| token.strip_prefix("u+"))
+    else {
+        return Err(format!("bad unicode-range {token:?}"));
+    };
+    let parse = |hex: &str| {
+        u32::from_str_radix(hex, 16).map_err(|e| format!("bad unicode-range {token:?}: {e}"))
+    };
+    let (start, end) = if let Some((start, end)) = hex.split_once('-') {
+        (parse(start)?, parse(end)?)
+    } else if hex.contains('?') {
+        (
+            parse(&hex.replace('?', "0"))?,
+            parse(&hex.replace('?', "F"))?,
+        )
+    } else {
+        let cp = parse(hex)?;
+        (cp, cp)
+    };
+    if start > end || end > ALL_CODEPOINTS.1 {
+        return Err(format!("bad unicode-range {token:?}"));
+    }
+    Ok((start, end))
+}
+
+/// Sort ranges, merging any that overlap or touch
+fn normalize(mut ranges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    ranges.sort();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some((_, prev_end)) if start <= prev_end.saturating_add(1) => {
+                *prev_end = (*prev_end).max(end)
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// The (font-family, slice) of each @font-face of a css2 response, in order
+///
+/// A slice is named by the comment before its @font-face, e.g. /* [42] */ or /* latin-ext */.
+pub fn parse_css2(css: &str) -> Result<Vec<(SmolStr, Slice)>, String> {
+    let segments = css.split("@font-face").collect::<Vec<_>>();
+    let mut faces = Vec::new();
+    for (prev, segment) in segments.iter().zip(segments.iter().skip(1)) {
+        // The comment, if any, is after the end of the previous @font-face
+        let preamble = prev.rsplit_once('}').map(|(_, p)| p).unwrap_or(prev);
+        let name = preamble
+            .rsplit_once("/*")
+            .and_then(|(_, comment)| comment.split_once("*/"))
+            .map(|(comment, _)| comment.trim())
+            .unwrap_or_default();
+
+        let Some(body) = segment
+            .split_once('{')
+            .and_then(|(_, body)| body.split_once('}'))
+            .map(|(body, _)| body)
+        else {
+            return Err(format!("unterminated @font-face {}", faces.len()));
+        };
+
+        let mut family = None;
+        let mut ranges = vec![ALL_CODEPOINTS];
+        for declaration in body.split(';') {
+            let Some((property, value)) = declaration.split_once(':') else {
+                continue;
+            };
+            match property.trim() {
+                "font-family" => family = Some(value.trim().trim_matches(['\'', '"']).into()),
+                "unicode-range" => {
+                    ranges = value
+                        .split(',')
+                        .map(|token| parse_range(token.trim()))
+                        .collect::<Result<_, _>>()?;
+                }
+                _ => (),
+            }
+        }
+        let Some(family) = family else {
+            return Err(format!("@font-face {} has no font-family", faces.len()));
+        };
+        faces.push((
+            family,
+            Slice {
+                name: name.into(),
+                ranges: normalize(ranges),
+            },
+        ));
+    }
+    Ok(faces)
+}
+
+/// The slices of each family we have a saved css2 response for
+#[derive(Default)]
+pub struct SliceTables {
+    by_family_name: HashMap<SmolStr, Vec<Slice>>,
+}
+
+impl SliceTables {
+    /// Read every .css file under d
+    ///
+    /// A family whose response lists several styles or weights has the same slices for each,
+    /// we keep one of each.
+    pub fn from_dir(d: &str) -> Self {
+        let mut tables = Self::default();
+        for e in WalkDir::new(d).into_iter() {
+            let Ok(e) = e else {
+                eprintln!("Walk error {e:?}");
+                continue;
+            };
+            let path = e.path();
+            if !path.is_file() || path.extension().is_none_or(|ext| ext != "css") {
+                continue;
+            }
+            let css =
+                fs::read_to_string(path).unwrap_or_else(|e| panic!("Unable to read {path:?}: {e}"));
+            let faces =
+                parse_css2(&css).unwrap_or_else(|e| panic!("Unable to parse {path:?}: {e}"));
+            for (family, slice) in faces {
+                let slices = tables
+                    .by_family_name
+                    .entry(FamilyName(family).lookup_key())
+                    .or_default();
+                if !slices.contains(&slice) {
+                    slices.push(slice);
+                }
+            }
+        }
+        eprintln!("{} families with slices", tables.by_family_name.len());
+        tables
+    }
+
+    pub fn slices(&self, family_name: &FamilyName) -> Option<&Vec<Slice>> {
+        self.by_family_name.get(&family_name.lookup_key())
+    }
+}
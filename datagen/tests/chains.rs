@@ -0,0 +1,34 @@
+//! Building chains from fonts.xml
+
+use datagen::{
+    chains::named_chain, font_binaries::FontBinaries, fonts_xml::Familyset, slices::SliceTables,
+};
+use itemizer::Error;
+
+#[test]
+fn unknown_head_is_an_error() {
+    let fonts = FontBinaries::from_dir(env!("CARGO_MANIFEST_DIR"));
+    assert!(matches!(
+        named_chain(
+            &Familyset::fonts_xml_for_googlefonts(),
+            &fonts,
+            &SliceTables::default(),
+            "sans-serf",
+        ),
+        Err(Error::UnknownFamily(head)) if head == "sans-serf"
+    ));
+}
+
+#[test]
+fn missing_head_binary_is_an_error() {
+    let fonts = FontBinaries::from_dir(env!("CARGO_MANIFEST_DIR"));
+    assert!(matches!(
+        named_chain(
+            &Familyset::fonts_xml_for_googlefonts(),
+            &fonts,
+            &SliceTables::default(),
+            "sans-serif",
+        ),
+        Err(Error::NoCoverage(_))
+    ));
+}
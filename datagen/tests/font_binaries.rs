@@ -0,0 +1,146 @@
+//! Reading what we need from font files
+
+use std::fs;
+
+use datagen::font_binaries::{Filename, FontBinaries};
+
+/// Big-endian writes in the layout of OpenType tables
+#[derive(Default)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+    fn u16(mut self, v: u16) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Self {
+        self.0.extend_from_slice(&v.to_be_bytes());
+        self
+    }
+}
+
+/// A font of just cmap and GSUB whose one empty glyph, 3, is mapped from ZWSP, ZWJ and FE0F
+///
+/// It ligates 👩‍🚀, glyphs 1 3 2, and 🏳️‍🌈, glyphs 4 3 3 5.
+fn shared_empty_glyph_font() -> Vec<u8> {
+    let cmap = [
+        (0x200b, 3),
+        (0x200d, 3),
+        (0xfe0f, 3),
+        (0x1f308, 5),
+        (0x1f3f3, 4),
+        (0x1f469, 1),
+        (0x1f680, 2),
+    ]
+    .into_iter()
+    .fold(
+        Bytes::default()
+            .u16(0)
+            .u16(1)
+            .u16(3)
+            .u16(10)
+            .u32(12)
+            .u16(12)
+            .u16(0)
+            .u32(16 + 12 * 7)
+            .u32(0)
+            .u32(7),
+        |bytes, (cp, gid)| bytes.u32(cp).u32(cp).u32(gid),
+    )
+    .0;
+
+    let gsub = Bytes::default()
+        // Header, then empty script and feature lists
+        .u16(1)
+        .u16(0)
+        .u16(10)
+        .u16(12)
+        .u16(14)
+        .u16(0)
+        .u16(0)
+        // One lookup of one ligature subtable
+        .u16(1)
+        .u16(4)
+        .u16(4)
+        .u16(0)
+        .u16(1)
+        .u16(8)
+        // The subtable, coverage at 10, ligature sets at 18 and 30
+        .u16(1)
+        .u16(10)
+        .u16(2)
+        .u16(18)
+        .u16(30)
+        .u16(1)
+        .u16(2)
+        .u16(1)
+        .u16(4)
+        // 1 3 2 => 6
+        .u16(1)
+        .u16(4)
+        .u16(6)
+        .u16(3)
+        .u16(3)
+        .u16(2)
+        // 4 3 3 5 => 7
+        .u16(1)
+        .u16(4)
+        .u16(7)
+        .u16(4)
+        .u16(3)
+        .u16(3)
+        .u16(5)
+        .0;
+
+    let gsub_offset = 12 + 16 * 2;
+    let cmap_offset = (gsub_offset + gsub.len() as u32).next_multiple_of(4);
+    let mut font = Bytes::default()
+        .u32(0x00010000)
+        .u16(2)
+        .u16(32)
+        .u16(1)
+        .u16(0)
+        .u32(u32::from_be_bytes(*b"GSUB"))
+        .u32(0)
+        .u32(gsub_offset)
+        .u32(gsub.len() as u32)
+        .u32(u32::from_be_bytes(*b"cmap"))
+        .u32(0)
+        .u32(cmap_offset)
+        .u32(cmap.len() as u32)
+        .0;
+    font.extend(gsub);
+    font.resize(cmap_offset as usize, 0);
+    font.extend(cmap);
+    font
+}
+
+#[test]
+fn sequences_through_a_shared_empty_glyph() {
+    let dir = format!("{}/shared_empty_glyph", env!("CARGO_TARGET_TMPDIR"));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(
+        format!("{dir}/EmojiSequences.ttf"),
+        shared_empty_glyph_font(),
+    )
+    .unwrap();
+
+    let sequences = FontBinaries::from_dir(&dir)
+        .sequences(&Filename("emojisequences.ttf".into()))
+        .unwrap();
+    assert!(
+        sequences.contains(&vec![0x1f469, 0x200d, 0x1f680]),
+        "{sequences:x?}"
+    );
+    assert!(
+        sequences.contains(&vec![0x1f3f3, 0x200d, 0x1f308]),
+        "{sequences:x?}"
+    );
+    assert!(
+        !sequences
+            .iter()
+            .flatten()
+            .any(|cp| *cp == 0x200b || *cp == 0xfe0f)
+    );
+}
@@ -0,0 +1,104 @@
+//! Reading unicode-range slices from saved css2 responses
+
+use datagen::{
+    font_binaries::FamilyName,
+    slices::{SliceTables, parse_css2},
+};
+use itemizer::fallback_chain::Slice;
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/css2");
+
+fn slice(name: &str, ranges: &[(u32, u32)]) -> Slice {
+    Slice {
+        name: name.into(),
+        ranges: ranges.to_vec(),
+    }
+}
+
+#[test]
+fn numbered_slices_are_named_by_their_comment() {
+    let css = include_str!("css2/noto_sans_jp.css");
+    let faces = parse_css2(css).unwrap();
+    assert_eq!(6, faces.len());
+    assert!(faces.iter().all(|(family, _)| family == "Noto Sans JP"));
+    assert_eq!(
+        vec!["[0]", "[119]", "latin", "[0]", "[119]", "latin"],
+        faces
+            .iter()
+            .map(|(_, slice)| slice.name.as_str())
+            .collect::<Vec<_>>()
+    );
+    // Sorted, adjacent ranges merged
+    assert_eq!(
+        slice(
+            "[119]",
+            &[
+                (0x4e00, 0x4e0f),
+                (0x4e16, 0x4e16),
+                (0x65e5, 0x65e5),
+                (0x672c, 0x672c),
+                (0x754c, 0x754c)
+            ]
+        ),
+        faces[1].1
+    );
+    assert_eq!(
+        Some(&(0x0152, 0x0153)),
+        faces[2].1.ranges.iter().find(|(start, _)| *start == 0x0152)
+    );
+}
+
+#[test]
+fn unicode_range_wildcards_and_ranges() {
+    let css = "/* mixed */
+@font-face {
+  font-family: \"Test\";
+  unicode-range: U+4??, U+0-7F, u+80-ff, U+1F600;
+}";
+    assert_eq!(
+        vec![(
+            "Test".into(),
+            slice("mixed", &[(0, 0xff), (0x400, 0x4ff), (0x1f600, 0x1f600)])
+        )],
+        parse_css2(css).unwrap()
+    );
+}
+
+#[test]
+fn face_without_unicode_range_covers_everything() {
+    let faces = parse_css2(include_str!("css2/symbols.css")).unwrap();
+    assert_eq!(
+        vec![("Noto Sans Symbols 2".into(), slice("", &[(0, 0x10ffff)]))],
+        faces
+    );
+}
+
+#[test]
+fn bad_faces_are_errors() {
+    for css in [
+        "@font-face { font-family: 'Test'; unicode-range: U+110000; }",
+        "@font-face { font-family: 'Test'; unicode-range: U+7F-0; }",
+        "@font-face { font-family: 'Test'; unicode-range: 41; }",
+        "@font-face { unicode-range: U+41; }",
+        "@font-face { font-family: 'Test';",
+    ] {
+        assert!(parse_css2(css).is_err(), "{css}");
+    }
+}
+
+#[test]
+fn weights_share_slices() {
+    let tables = SliceTables::from_dir(FIXTURES);
+    let jp = tables.slices(&FamilyName("Noto Sans JP".into())).unwrap();
+    assert_eq!(
+        vec!["[0]", "[119]", "latin"],
+        jp.iter().map(|s| s.name.as_str()).collect::<Vec<_>>()
+    );
+    // Family names match regardless of case and spacing, as they do for font binaries
+    assert!(
+        tables
+            .slices(&FamilyName("notosanssymbols2".into()))
+            .is_some()
+    );
+    assert!(tables.slices(&FamilyName("Roboto".into())).is_none());
+}
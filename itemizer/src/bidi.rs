@@ -0,0 +1,42 @@
+//! Bidi embedding levels per the [Unicode Bidi Algorithm](https://www.unicode.org/reports/tr9/)
+//! and the mirroring they imply
+
+use icu_properties::bidi_data;
+use unicode_bidi::BidiInfo;
+
+/// The resolved embedding level of every byte of text, each paragraph taking its direction from
+/// its first strong character
+pub(crate) fn levels(text: &str) -> Vec<u8> {
+    BidiInfo::new(text, None)
+        .levels
+        .into_iter()
+        .map(|level| level.number())
+        .collect()
+}
+
+/// Text as a shaper will draw it, each mirrored character at an odd, right to left, level
+/// replaced by its mirror, e.g. ( by ), if any are
+///
+/// Offsets into the result are offsets into text: a character whose mirror encodes to a
+/// different length in UTF-8 is left alone.
+pub(crate) fn mirrored(text: &str, levels: &[u8]) -> Option<String> {
+    let mirroring = bidi_data::bidi_auxiliary_properties();
+    let mut result: Option<String> = None;
+    for (offset, c) in text.char_indices() {
+        if levels[offset].is_multiple_of(2) {
+            continue;
+        }
+        let Some(mirror) = mirroring.get32_mirroring_props(c as u32).mirroring_glyph else {
+            continue;
+        };
+        if mirror.len_utf8() != c.len_utf8() {
+            continue;
+        }
+        let result = result.get_or_insert_with(|| text.to_string());
+        result.replace_range(
+            offset..offset + c.len_utf8(),
+            mirror.encode_utf8(&mut [0; 4]),
+        );
+    }
+    result
+}
@@ -0,0 +1,448 @@
+//! A compact, versioned binary serialization of a [FallbackChain].
+//!
+//! Lets a compiled chain be shipped as a single data file and itemized against
+//! without any font binaries. All integers are little-endian. Layout:
+//!
+//! ```text
+//! magic      b"ITMZ"
+//! version    u16
+//! name       string
+//! stats      u32 each of codepoints, distinct, unambiguous_conflict, conflict_groups, mappings,
+//!            lang_mappings
+//! families   u32 count, then per family
+//!   name       string
+//!   lang       string, empty if none
+//!   variant    u8, 0 default, 1 compact, 2 elegant
+//!   emoji      u8, 1 for a color emoji family, else 0
+//!   coverage   u32 count, then (start u32, end u32) inclusive ranges, sorted
+//!   sequences  u8 1 if the family has sequences, else 0, then if it does
+//!              u32 count, then per sequence u32 length and that many u32 codepoints, sorted
+//!   cost       u8 0 for none, 1 for a whole file, 2 per codepoint, then if any u32 bytes
+//!   slices     u8 1 if the family has slices, else 0, then if it does
+//!              u32 count, then per slice name string, u32 count and (start u32, end u32)
+//!              inclusive ranges, sorted
+//! mappings   u32 count, then (start u32, end u32, family index u32), sorted
+//! lang tables u32 count, then per table
+//!   family     u32 index of the family whose lang holds the locale, 0xFFFFFFFF for no locale
+//!   locale     u32 index of the locale in the family lang
+//!   mappings   as above
+//!
+//! string     u32 byte length, then utf-8 bytes
+//! ```
+//!
+//! Loading isn't zero-copy: the file is read into memory and every coverage range expanded into
+//! the codepoints of a [Family]. Ranges must lie within Unicode, 0..=0x10FFFF, so a corrupt
+//! file can't make that expansion unbounded.
+
+use std::{collections::BTreeSet, fs, path::Path};
+
+use smol_str::SmolStr;
+
+use crate::{
+    Error,
+    fallback_chain::{
+        ChainStats, CodepointMapping, Cost, FallbackChain, Family, FontIdx, LangMappings, Slice,
+        Variant, family_locales,
+    },
+};
+
+const MAGIC: &[u8; 4] = b"ITMZ";
+
+/// The family index of the lang table for requests without a locale
+const NO_LOCALE: u32 = u32::MAX;
+
+/// The format version written by [FallbackChain::save]; [FallbackChain::load] accepts only this version.
+pub const VERSION: u16 = 8;
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn u16(&mut self, v: u16) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn u32(&mut self, v: u32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.u32(len.try_into().expect("Chain too large to serialize"));
+    }
+
+    fn str(&mut self, v: &str) {
+        self.len(v.len());
+        self.0.extend_from_slice(v.as_bytes());
+    }
+
+    fn mappings(&mut self, mappings: &[CodepointMapping]) {
+        self.len(mappings.len());
+        for mapping in mappings.iter() {
+            self.u32(mapping.start);
+            self.u32(mapping.end);
+            self.len(mapping.font.0);
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let Some(bytes) = self.data.get(self.pos..self.pos.saturating_add(len)) else {
+            return Err(Error::MalformedChainData(format!(
+                "unexpected end of data reading {len} bytes at {}",
+                self.pos
+            )));
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn len(&mut self) -> Result<usize, Error> {
+        let len = self.u32()? as usize;
+        // Every counted item takes at least a byte, reject absurd counts before allocating
+        if len > self.data.len() - self.pos {
+            return Err(Error::MalformedChainData(format!(
+                "count {len} at {} exceeds remaining data",
+                self.pos - 4
+            )));
+        }
+        Ok(len)
+    }
+
+    fn str(&mut self) -> Result<SmolStr, Error> {
+        let len = self.len()?;
+        let pos = self.pos;
+        std::str::from_utf8(self.bytes(len)?)
+            .map(SmolStr::from)
+            .map_err(|e| Error::MalformedChainData(format!("invalid utf-8 at {pos}: {e}")))
+    }
+
+    /// Reads an inclusive range, rejecting ranges that aren't sorted and disjoint from the previous one
+    fn range(&mut self, prev_end: Option<u32>) -> Result<(u32, u32), Error> {
+        let pos = self.pos;
+        let start = self.u32()?;
+        let end = self.u32()?;
+        if start > end || prev_end.is_some_and(|prev_end| start <= prev_end) {
+            return Err(Error::MalformedChainData(format!(
+                "range {start}..={end} at {pos} is inverted or out of order"
+            )));
+        }
+        if end > char::MAX as u32 {
+            return Err(Error::MalformedChainData(format!(
+                "range {start}..={end} at {pos} is beyond Unicode"
+            )));
+        }
+        Ok((start, end))
+    }
+
+    /// Reads a table of mappings to the first num_families families
+    fn mappings(&mut self, num_families: usize) -> Result<Vec<CodepointMapping>, Error> {
+        let num_mappings = self.len()?;
+        let mut mappings = Vec::with_capacity(num_mappings);
+        let mut prev_end = None;
+        for _ in 0..num_mappings {
+            let (start, end) = self.range(prev_end)?;
+            prev_end = Some(end);
+            let font = self.u32()? as usize;
+            if font >= num_families {
+                return Err(Error::MalformedChainData(format!(
+                    "mapping {start}..={end} refers to family {font} but there are only {num_families}"
+                )));
+            }
+            mappings.push(CodepointMapping {
+                start,
+                end,
+                font: FontIdx(font),
+            });
+        }
+        Ok(mappings)
+    }
+}
+
+impl FallbackChain {
+    /// Serialize to the binary format described in [crate::chain_file]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+        w.0.extend_from_slice(MAGIC);
+        w.u16(VERSION);
+        w.str(&self.name);
+        for stat in [
+            self.stats.codepoints,
+            self.stats.distinct,
+            self.stats.unambiguous_conflict,
+            self.stats.conflict_groups,
+            self.stats.mappings,
+            self.stats.lang_mappings,
+        ] {
+            w.len(stat);
+        }
+
+        w.len(self.families.len());
+        for family in self.families.iter() {
+            w.str(&family.family_name);
+            w.str(family.lang.as_deref().unwrap_or_default());
+            w.u8(match family.variant {
+                Variant::Default => 0,
+                Variant::Compact => 1,
+                Variant::Elegant => 2,
+            });
+            w.u8(family.emoji.into());
+
+            let mut ranges: Vec<(u32, u32)> = Vec::new();
+            for cp in family.codepoints.iter().copied() {
+                match ranges.last_mut() {
+                    Some((_, end)) if *end + 1 == cp => *end = cp,
+                    _ => ranges.push((cp, cp)),
+                }
+            }
+            w.len(ranges.len());
+            for (start, end) in ranges {
+                w.u32(start);
+                w.u32(end);
+            }
+
+            w.u8(family.sequences.is_some().into());
+            if let Some(sequences) = family.sequences.as_ref() {
+                w.len(sequences.len());
+                for sequence in sequences.iter() {
+                    w.len(sequence.len());
+                    for cp in sequence.iter() {
+                        w.u32(*cp);
+                    }
+                }
+            }
+
+            match family.cost {
+                None => w.u8(0),
+                Some(Cost::File(bytes)) => {
+                    w.u8(1);
+                    w.u32(bytes);
+                }
+                Some(Cost::PerCodepoint(bytes)) => {
+                    w.u8(2);
+                    w.u32(bytes);
+                }
+            }
+
+            w.u8(family.slices.is_some().into());
+            if let Some(slices) = family.slices.as_ref() {
+                w.len(slices.len());
+                for slice in slices.iter() {
+                    w.str(&slice.name);
+                    w.len(slice.ranges.len());
+                    for (start, end) in slice.ranges.iter() {
+                        w.u32(*start);
+                        w.u32(*end);
+                    }
+                }
+            }
+        }
+
+        w.mappings(&self.mappings);
+
+        w.len(self.lang_mappings.len());
+        for table in self.lang_mappings.iter() {
+            match table.source {
+                Some((font, idx)) => {
+                    w.len(font.0);
+                    w.len(idx);
+                }
+                None => {
+                    w.u32(NO_LOCALE);
+                    w.u32(0);
+                }
+            }
+            w.mappings(&table.mappings);
+        }
+        w.0
+    }
+
+    /// Deserialize from the binary format described in [crate::chain_file]
+    pub fn from_bytes(data: &[u8]) -> Result<Self, Error> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(MAGIC.len())? != MAGIC {
+            return Err(Error::MalformedChainData("bad magic".to_string()));
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(Error::UnsupportedChainVersion(version));
+        }
+        let name = r.str()?;
+        let stats = ChainStats {
+            codepoints: r.u32()? as usize,
+            distinct: r.u32()? as usize,
+            unambiguous_conflict: r.u32()? as usize,
+            conflict_groups: r.u32()? as usize,
+            mappings: r.u32()? as usize,
+            lang_mappings: r.u32()? as usize,
+        };
+
+        let num_families = r.len()?;
+        let mut families = Vec::with_capacity(num_families);
+        for _ in 0..num_families {
+            let family_name = r.str()?;
+            let lang = Some(r.str()?).filter(|l| !l.is_empty());
+            let variant = match r.u8()? {
+                0 => Variant::Default,
+                1 => Variant::Compact,
+                2 => Variant::Elegant,
+                v => {
+                    return Err(Error::MalformedChainData(format!(
+                        "unknown variant {v} for {family_name}"
+                    )));
+                }
+            };
+            let emoji = match r.u8()? {
+                0 => false,
+                1 => true,
+                v => {
+                    return Err(Error::MalformedChainData(format!(
+                        "bad emoji flag {v} for {family_name}"
+                    )));
+                }
+            };
+            let mut codepoints = BTreeSet::new();
+            let mut prev_end = None;
+            for _ in 0..r.len()? {
+                let (start, end) = r.range(prev_end)?;
+                codepoints.extend(start..=end);
+                prev_end = Some(end);
+            }
+            let sequences = match r.u8()? {
+                0 => None,
+                1 => {
+                    let mut sequences = BTreeSet::new();
+                    for _ in 0..r.len()? {
+                        let len = r.len()?;
+                        let sequence = (0..len).map(|_| r.u32()).collect::<Result<Vec<_>, _>>()?;
+                        sequences.insert(sequence);
+                    }
+                    Some(sequences)
+                }
+                v => {
+                    return Err(Error::MalformedChainData(format!(
+                        "bad sequences flag {v} for {family_name}"
+                    )));
+                }
+            };
+            let cost = match r.u8()? {
+                0 => None,
+                1 => Some(Cost::File(r.u32()?)),
+                2 => Some(Cost::PerCodepoint(r.u32()?)),
+                v => {
+                    return Err(Error::MalformedChainData(format!(
+                        "unknown cost {v} for {family_name}"
+                    )));
+                }
+            };
+            let slices = match r.u8()? {
+                0 => None,
+                1 => {
+                    let mut slices = Vec::new();
+                    for _ in 0..r.len()? {
+                        let name = r.str()?;
+                        let mut ranges = Vec::new();
+                        let mut prev_end = None;
+                        for _ in 0..r.len()? {
+                            let (start, end) = r.range(prev_end)?;
+                            ranges.push((start, end));
+                            prev_end = Some(end);
+                        }
+                        slices.push(Slice { name, ranges });
+                    }
+                    Some(slices)
+                }
+                v => {
+                    return Err(Error::MalformedChainData(format!(
+                        "bad slices flag {v} for {family_name}"
+                    )));
+                }
+            };
+            families.push(Family {
+                family_name,
+                lang,
+                variant,
+                emoji,
+                codepoints,
+                sequences,
+                cost,
+                slices,
+            });
+        }
+
+        if families.is_empty() {
+            return Err(Error::EmptyChain);
+        }
+        let locales = family_locales(&families)?;
+
+        let mappings = r.mappings(families.len())?;
+
+        let mut chain = FallbackChain {
+            name,
+            families,
+            mappings,
+            stats,
+            locales,
+            lang_mappings: Vec::new(),
+        };
+
+        for _ in 0..r.len()? {
+            let pos = r.pos;
+            let source = match (r.u32()?, r.u32()?) {
+                (NO_LOCALE, _) => None,
+                (font, idx) => Some((FontIdx(font as usize), idx as usize)),
+            };
+            let Some(request) = chain.lang_request(source) else {
+                return Err(Error::MalformedChainData(format!(
+                    "lang table at {pos} refers to a locale that doesn't exist"
+                )));
+            };
+            let mappings = r.mappings(chain.families.len())?;
+            chain.lang_mappings.push(LangMappings {
+                source,
+                request,
+                mappings,
+            });
+        }
+
+        if r.pos != data.len() {
+            return Err(Error::MalformedChainData(format!(
+                "{} unexpected trailing bytes",
+                data.len() - r.pos
+            )));
+        }
+
+        Ok(chain)
+    }
+
+    /// Write the binary format described in [crate::chain_file] to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+
+    /// Read a chain written by [FallbackChain::save]
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
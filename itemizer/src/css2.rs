@@ -0,0 +1,211 @@
+//! Google Fonts [css2](https://developers.google.com/fonts/docs/css2) requests for the subset of
+//! each family an itemization needs
+
+use std::{collections::BTreeSet, fmt::Write, str::FromStr};
+
+use smol_str::SmolStr;
+
+use crate::{
+    Error, Run,
+    fallback_chain::{FallbackChain, Family},
+};
+
+/// Where css2 requests go
+pub const CSS2_API: &str = "https://fonts.googleapis.com/css2";
+
+/// Longer URLs are liable to be rejected along the way
+pub const DEFAULT_MAX_URL_LEN: usize = 2048;
+
+/// The css font-display to request, how text renders while the font loads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontDisplay {
+    Auto,
+    Block,
+    Swap,
+    Fallback,
+    Optional,
+}
+
+impl FontDisplay {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FontDisplay::Auto => "auto",
+            FontDisplay::Block => "block",
+            FontDisplay::Swap => "swap",
+            FontDisplay::Fallback => "fallback",
+            FontDisplay::Optional => "optional",
+        }
+    }
+}
+
+impl FromStr for FontDisplay {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(FontDisplay::Auto),
+            "block" => Ok(FontDisplay::Block),
+            "swap" => Ok(FontDisplay::Swap),
+            "fallback" => Ok(FontDisplay::Fallback),
+            "optional" => Ok(FontDisplay::Optional),
+            _ => Err(Error::InvalidFontDisplay(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Css2Options {
+    /// Sent as display= if set, otherwise Google Fonts picks
+    pub display: Option<FontDisplay>,
+    /// Characters are split across as many URLs as it takes to keep each this short, except
+    /// that a URL always has at least one character
+    pub max_url_len: usize,
+}
+
+impl Default for Css2Options {
+    fn default() -> Self {
+        Self {
+            display: None,
+            max_url_len: DEFAULT_MAX_URL_LEN,
+        }
+    }
+}
+
+/// The distinct characters a family has to draw
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FamilyText<'a> {
+    pub family: &'a Family,
+    /// Control characters, e.g. newlines, are left out, no font need draw them
+    pub chars: BTreeSet<char>,
+    /// The graphemes of more than one character, e.g. an emoji ZWJ sequence or a letter and its
+    /// accent, that must be in the same subset to draw as one
+    pub clusters: BTreeSet<SmolStr>,
+}
+
+/// Group the text of runs by family, families in the order they first appear
+///
+/// Runs without a family are skipped, no request will help them.
+pub fn family_texts<'a>(text: &str, runs: &[Run<'a>]) -> Vec<FamilyText<'a>> {
+    let mut result: Vec<FamilyText<'a>> = Vec::new();
+    for run in runs {
+        let Some(family) = run.family else {
+            continue;
+        };
+        let run_text = &text[run.start..run.end];
+        let chars = run_text.chars().filter(|c| !c.is_control());
+        let clusters = FallbackChain::graphemes(run_text)
+            .into_iter()
+            .map(|(start, end)| {
+                run_text[start..end]
+                    .chars()
+                    .filter(|c| !c.is_control())
+                    .collect::<SmolStr>()
+            })
+            .filter(|cluster| cluster.chars().nth(1).is_some());
+        let ft = match result.iter().position(|ft| std::ptr::eq(ft.family, family)) {
+            Some(idx) => &mut result[idx],
+            None => {
+                result.push(FamilyText {
+                    family,
+                    chars: BTreeSet::new(),
+                    clusters: BTreeSet::new(),
+                });
+                result.last_mut().unwrap()
+            }
+        };
+        ft.chars.extend(chars);
+        ft.clusters.extend(clusters);
+    }
+    result.retain(|ft| !ft.chars.is_empty());
+    result
+}
+
+/// Append s to dest, percent encoding all but the unreserved characters of RFC 3986
+fn percent_encode(s: &str, dest: &mut String) {
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            dest.push(b as char);
+        } else {
+            write!(dest, "%{b:02X}").unwrap();
+        }
+    }
+}
+
+/// The css2 URL for all of family, e.g. `https://fonts.googleapis.com/css2?family=Noto+Sans+JP`
+///
+/// Google Fonts answers with an @font-face per unicode-range slice of the family, the browser
+/// downloading only the slices a page uses, see [Family::slices].
+pub fn family_url(family: &Family, options: &Css2Options) -> String {
+    let mut url = format!("{CSS2_API}?family=");
+    for (i, word) in family.family_name.split_whitespace().enumerate() {
+        if i > 0 {
+            url.push('+');
+        }
+        percent_encode(word, &mut url);
+    }
+    if let Some(display) = options.display {
+        url.push_str("&display=");
+        url.push_str(display.as_str());
+    }
+    url
+}
+
+impl FamilyText<'_> {
+    /// css2 URLs for a subset of the family with just our characters, e.g.
+    /// `https://fonts.googleapis.com/css2?family=Noto+Sans+JP&text=%E4%B8%96%E7%95%8C`
+    ///
+    /// The characters of each of [FamilyText::clusters] go in the same URL, a character
+    /// shared by clusters, e.g. ZWJ, going in each URL that needs it. Clusters some of whose
+    /// characters we don't have are split like any other characters.
+    pub fn urls(&self, options: &Css2Options) -> Vec<String> {
+        let mut prefix = family_url(self.family, options);
+        prefix.push_str("&text=");
+
+        let mut units = self
+            .clusters
+            .iter()
+            .map(|cluster| cluster.chars().collect::<BTreeSet<_>>())
+            .filter(|chars| chars.is_subset(&self.chars))
+            .collect::<Vec<_>>();
+        let clustered = units.iter().flatten().copied().collect::<BTreeSet<_>>();
+        units.extend(
+            self.chars
+                .difference(&clustered)
+                .map(|c| BTreeSet::from([*c])),
+        );
+        units.sort();
+
+        let mut urls = Vec::new();
+        let mut url = prefix.clone();
+        let mut in_url = BTreeSet::new();
+        let mut encoded = String::new();
+        for unit in units {
+            encoded.clear();
+            for c in unit.difference(&in_url) {
+                percent_encode(c.encode_utf8(&mut [0; 4]), &mut encoded);
+            }
+            if url.len() > prefix.len() && url.len() + encoded.len() > options.max_url_len {
+                urls.push(std::mem::replace(&mut url, prefix.clone()));
+                in_url.clear();
+                encoded.clear();
+                for c in unit.iter() {
+                    percent_encode(c.encode_utf8(&mut [0; 4]), &mut encoded);
+                }
+            }
+            url.push_str(&encoded);
+            in_url.extend(unit);
+        }
+        if url.len() > prefix.len() {
+            urls.push(url);
+        }
+        urls
+    }
+}
+
+/// css2 URLs for every family the runs of text use, see [FamilyText::urls]
+pub fn urls(text: &str, runs: &[Run], options: &Css2Options) -> Vec<String> {
+    family_texts(text, runs)
+        .iter()
+        .flat_map(|ft| ft.urls(options))
+        .collect()
+}
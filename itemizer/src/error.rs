@@ -1,14 +1,55 @@
-use std::fmt;
+use std::{fmt, io};
 
-#[derive(Debug, Clone)]
+use smol_str::SmolStr;
+
+#[derive(Debug)]
 pub enum Error {
-    TBD,
+    /// A chain must have at least one family
+    EmptyChain,
+    /// The named family doesn't support any codepoints, e.g. because its font binary is missing
+    NoCoverage(SmolStr),
+    /// There's no family by that name to head a chain, e.g. in fonts.xml
+    UnknownFamily(SmolStr),
+    /// A language that isn't a comma separated list of BCP 47 tags
+    InvalidLanguageTag(SmolStr),
+    /// Text, in utf-8 bytes, beyond what we are willing to itemize
+    TextTooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// Not a css font-display value, e.g. swap
+    InvalidFontDisplay(SmolStr),
+    Io(io::Error),
+    MalformedChainData(String),
+    UnsupportedChainVersion(u16),
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::TBD => f.write_str("TBD"),
+            Error::EmptyChain => f.write_str("a fallback chain must have at least one family"),
+            Error::NoCoverage(family) => write!(f, "{family} doesn't support any codepoints"),
+            Error::UnknownFamily(family) => write!(f, "no family named {family:?}"),
+            Error::InvalidLanguageTag(lang) => write!(f, "invalid language tag {lang:?}"),
+            Error::TextTooLarge { len, max } => {
+                write!(f, "text of {len} bytes exceeds the limit of {max} bytes")
+            }
+            Error::InvalidFontDisplay(display) => write!(f, "invalid font-display {display:?}"),
+            Error::Io(e) => write!(f, "io error: {e}"),
+            Error::MalformedChainData(msg) => write!(f, "malformed chain data: {msg}"),
+            Error::UnsupportedChainVersion(v) => write!(
+                f,
+                "unsupported chain data version {v}, expected {}",
+                crate::chain_file::VERSION
+            ),
         }
     }
 }
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
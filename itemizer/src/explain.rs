@@ -0,0 +1,224 @@
+//! Records of why each grapheme landed in the family it did, for debugging fallback decisions.
+
+use std::fmt;
+
+use crate::{
+    Error, ItemizeOptions, bidi,
+    fallback_chain::{FallbackChain, Family, FontIdx, Score},
+    locale::LocaleList,
+};
+
+/// How [FallbackChain::itemize] located the family for a grapheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchPath {
+    /// A single codepoint found in the table of codepoints with only one sensible family
+    Jump,
+    /// A single codepoint found in the table of codepoints whose winner depends on the requested lang
+    LangJump,
+    /// Scored every family in the chain
+    Walk,
+    /// Scored every family in the chain, none supported the grapheme
+    WalkToEof,
+    /// Kept the family of the previous grapheme, as Android does for some punctuation, or
+    /// because nothing in the grapheme needs drawing
+    Sticky,
+    /// No family supports the whole grapheme so it was split, each part going to the family
+    /// that supports the longest run of it
+    Partial,
+    /// Moved, with the rest of its word, to the first family that supports the whole word
+    Word,
+    /// Moved to one of the fewest families that cover the whole text
+    Cover,
+    /// Moved to the one family that covers the most of the text
+    Headline,
+}
+
+impl fmt::Display for MatchPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MatchPath::Jump => "jump",
+            MatchPath::LangJump => "lang_jump",
+            MatchPath::Walk => "walk",
+            MatchPath::WalkToEof => "walk_to_eof",
+            MatchPath::Sticky => "sticky",
+            MatchPath::Partial => "partial",
+            MatchPath::Word => "word",
+            MatchPath::Cover => "cover",
+            MatchPath::Headline => "headline",
+        })
+    }
+}
+
+/// Why the winning family won
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// No family supports the grapheme
+    Unsupported,
+    /// The only family that supports the grapheme
+    OnlySupporter,
+    /// The head of the chain supports the grapheme and is preferred to all alternatives
+    HeadPreference,
+    /// The family suits the emoji or text presentation requested by a variation selector
+    Presentation,
+    /// The family lang matched the requested lang better than the alternatives
+    LangMatch,
+    /// The family variant suits the request, e.g. compact over elegant
+    VariantMatch,
+    /// Several families scored equally, the earliest in the chain wins
+    FirstInChain,
+    /// The previous grapheme's family supports this one and keeps it
+    Continuity,
+    /// No family supports the whole grapheme, this family supports the most of it
+    PartialCoverage,
+    /// The family supports every grapheme of the word, see [ItemizeOptions::stable_words]
+    WholeWord,
+    /// The family is part of the cheapest cover of the text, see
+    /// [ItemizeOptions::minimize_families] and [ItemizeOptions::minimize_download]
+    Cover,
+    /// The family covers the most of the text, see [ItemizeOptions::headline]
+    Headline,
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Unsupported => "unsupported",
+            Reason::OnlySupporter => "only supporter",
+            Reason::HeadPreference => "head preference",
+            Reason::Presentation => "presentation",
+            Reason::LangMatch => "lang match",
+            Reason::VariantMatch => "variant match",
+            Reason::FirstInChain => "first in chain",
+            Reason::Continuity => "continuity",
+            Reason::PartialCoverage => "partial coverage",
+            Reason::WholeWord => "whole word",
+            Reason::Cover => "cover",
+            Reason::Headline => "headline",
+        })
+    }
+}
+
+/// A family that supports a grapheme and the score [FallbackChain] gave it
+#[derive(Debug, Clone)]
+pub struct Candidate<'a> {
+    pub family: &'a Family,
+    pub score: Score,
+}
+
+/// The decision made for a single grapheme, or part of one if no family supports all of it
+#[derive(Debug, Clone)]
+pub struct Decision<'a> {
+    /// Byte offset of the start of the grapheme, or part
+    pub start: usize,
+    /// Byte offset of the end of the grapheme, or part
+    pub end: usize,
+    pub path: MatchPath,
+    /// Every family that supports the grapheme, or part, in chain order
+    pub candidates: Vec<Candidate<'a>>,
+    pub family: Option<&'a Family>,
+    pub reason: Reason,
+}
+
+impl FallbackChain {
+    /// Like [FallbackChain::itemize] but produces a [Decision] per grapheme, or part of a split
+    /// grapheme, explaining the choice made
+    pub fn explain<'chain>(
+        &'chain self,
+        text: &str,
+        lang: &str,
+    ) -> Result<Vec<Decision<'chain>>, Error> {
+        self.explain_locales(text, &LocaleList::parse(lang)?)
+    }
+
+    /// Like [FallbackChain::explain] for an already parsed list of preferred locales
+    pub fn explain_locales<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+    ) -> Result<Vec<Decision<'chain>>, Error> {
+        self.explain_with_options(text, request, &ItemizeOptions::default())
+    }
+
+    /// Like [FallbackChain::explain_locales] deciding as
+    /// [FallbackChain::itemize_with_options] does with the same options
+    ///
+    /// Options that only split runs, [ItemizeOptions::script_runs] and the levels of
+    /// [ItemizeOptions::bidi], make no difference but bidi mirroring does.
+    pub fn explain_with_options<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+        options: &ItemizeOptions,
+    ) -> Result<Vec<Decision<'chain>>, Error> {
+        Self::check_input(text)?;
+        let drawn = options
+            .bidi
+            .then(|| bidi::mirrored(text, &bidi::levels(text)))
+            .flatten();
+        let text = drawn.as_deref().unwrap_or(text);
+        let pieces = self.resolve_text(text, text, request, options).pieces;
+        Ok(pieces
+            .into_iter()
+            .map(|piece| {
+                let (start, end, font, path) = (piece.start, piece.end, piece.font, piece.path);
+                let grapheme = &text[start..end];
+                let scores = (0..self.families.len())
+                    .map(FontIdx)
+                    .map(|font| (font, self.score(font, request, grapheme)))
+                    .filter(|(_, score)| score.is_supported())
+                    .collect::<Vec<_>>();
+                let reason = match font {
+                    None => Reason::Unsupported,
+                    Some(_) if path == MatchPath::Sticky => Reason::Continuity,
+                    Some(_) if path == MatchPath::Partial => Reason::PartialCoverage,
+                    Some(_) if path == MatchPath::Word => Reason::WholeWord,
+                    Some(_) if path == MatchPath::Cover => Reason::Cover,
+                    Some(_) if path == MatchPath::Headline => Reason::Headline,
+                    Some(_) if scores.len() == 1 => Reason::OnlySupporter,
+                    Some(font) if path == MatchPath::Jump => {
+                        debug_assert_eq!(font, FontIdx(0));
+                        Reason::HeadPreference
+                    }
+                    Some(font) => {
+                        let score = scores
+                            .iter()
+                            .find(|(f, _)| *f == font)
+                            .map(|(_, s)| *s)
+                            .unwrap_or(Score::UNSUPPORTED);
+                        let runner_up = scores
+                            .iter()
+                            .filter(|(f, _)| *f != font)
+                            .map(|(_, s)| *s)
+                            .max()
+                            .unwrap_or(Score::UNSUPPORTED);
+                        if score == Score::FIRST_FONT {
+                            Reason::HeadPreference
+                        } else if score == runner_up {
+                            Reason::FirstInChain
+                        } else if score.coverage() != runner_up.coverage() {
+                            Reason::Presentation
+                        } else if score.locale() != runner_up.locale() {
+                            Reason::LangMatch
+                        } else {
+                            Reason::VariantMatch
+                        }
+                    }
+                };
+                Decision {
+                    start,
+                    end,
+                    path,
+                    candidates: scores
+                        .into_iter()
+                        .map(|(font, score)| Candidate {
+                            family: &self.families[font.0],
+                            score,
+                        })
+                        .collect(),
+                    family: font.map(|font| &self.families[font.0]),
+                    reason,
+                }
+            })
+            .collect())
+    }
+}
@@ -1,271 +1,1398 @@
 //! A font fallback chain, against which one might itemize some text.
 
 use std::{
-    cmp::Ordering,
+    cmp::{Ordering, Reverse},
     collections::{BTreeSet, HashMap, HashSet},
+    fmt,
+    iter::once,
+    ptr,
 };
 
-use icu_segmenter::GraphemeClusterSegmenter;
+use icu_locid::subtags::{Script, script};
+use icu_properties::sets;
+use icu_segmenter::{GraphemeClusterSegmenter, WordSegmenter};
 use itertools::Itertools;
 use smol_str::SmolStr;
+use tracing::{debug, trace};
 
-use crate::{Error, Run};
+use crate::{
+    Direction, Error, Headline, ItemizeOptions, MissingCoverage, Run, bidi,
+    explain::MatchPath,
+    locale::{EmojiStyle, LocaleList},
+    script::{base_script, extends_to, is_neutral, script_runs},
+};
+
+/// The largest text, in utf-8 bytes, [FallbackChain::itemize] will accept
+pub const MAX_TEXT_LEN: usize = 16 * 1024 * 1024;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
-struct FontIdx(usize);
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub(crate) struct FontIdx(pub(crate) usize);
+
+/// Android's font variant, <https://developer.android.com/ndk/reference/group/font>
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Variant {
+    #[default]
+    Default,
+    Compact,
+    Elegant,
+}
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Family {
     pub family_name: SmolStr,
     pub lang: Option<SmolStr>,
+    pub variant: Variant,
+    /// A color emoji family, preferred for emoji presentation and avoided for text presentation
+    pub emoji: bool,
     pub codepoints: BTreeSet<u32>,
+    /// The multi-codepoint sequences, without variation selectors, the family draws as a single
+    /// glyph, e.g. from the ligatures in an emoji font's GSUB
+    ///
+    /// If set, a grapheme that needs more than one glyph is only supported if it's one of these.
+    /// None if we don't know, in which case supporting every codepoint is enough.
+    pub sequences: Option<BTreeSet<Vec<u32>>>,
+    /// What downloading the family costs, if known
+    pub cost: Option<Cost>,
+    /// The unicode-range slices Google Fonts serves the family as, in the order its css2
+    /// response lists them, if known
+    pub slices: Option<Vec<Slice>>,
+}
+
+/// One @font-face of a css2 response, the part of a family the browser downloads only if the
+/// page uses a codepoint in its unicode-range
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Slice {
+    /// The comment Google Fonts puts before the @font-face, e.g. "[42]" or "latin-ext", if any
+    pub name: SmolStr,
+    /// Inclusive codepoint ranges, sorted and disjoint
+    pub ranges: Vec<(u32, u32)>,
+}
+
+impl Slice {
+    pub fn contains(&self, cp: u32) -> bool {
+        self.ranges
+            .binary_search_by(|(start, end)| {
+                if *end < cp {
+                    Ordering::Less
+                } else if *start > cp {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .is_ok()
+    }
+}
+
+impl Family {
+    /// A family we know nothing about but its name, [FallbackChain::for_fonts] fills in its
+    /// codepoints
+    pub fn new(family_name: impl Into<SmolStr>) -> Self {
+        Self {
+            family_name: family_name.into(),
+            lang: None,
+            variant: Variant::Default,
+            emoji: false,
+            codepoints: Default::default(),
+            sequences: None,
+            cost: None,
+            slices: None,
+        }
+    }
+
+    /// The indices of the [Family::slices] a browser would download to draw chars, None if we
+    /// don't know the family's slices
+    ///
+    /// Slices may overlap, in which case every slice with the codepoint is downloaded.
+    pub fn slices_for(&self, chars: impl IntoIterator<Item = char>) -> Option<BTreeSet<usize>> {
+        let slices = self.slices.as_ref()?;
+        let mut touched = BTreeSet::new();
+        for c in chars {
+            touched.extend(
+                slices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, slice)| slice.contains(c as u32))
+                    .map(|(idx, _)| idx),
+            );
+        }
+        Some(touched)
+    }
+}
+
+/// What downloading a family costs, e.g. for [crate::ItemizeOptions::minimize_download]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Cost {
+    /// The whole file, this many bytes, however little of it is used
+    File(u32),
+    /// A subset, about this many bytes per codepoint in it
+    PerCodepoint(u32),
+}
+
+/// A [Cost] as the set cover sees it
+#[derive(Debug, Clone, Copy)]
+struct Price {
+    /// Paid once if the family is used at all
+    fixed: u64,
+    /// Paid for each distinct codepoint the family draws
+    per_codepoint: u64,
+}
+
+/// How well a family suits a grapheme, higher is better
+///
+/// Mirrors Minikin's calcFamilyScore: the coverage score is most significant, then locale, then variant.
+/// See <https://cs.android.com/android/platform/superproject/main/+/main:frameworks/minikin/libs/minikin/FontCollection.cpp>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Score(pub u32);
+
+impl Score {
+    /// The family doesn't support the grapheme
+    pub const UNSUPPORTED: Score = Score(0);
+    /// The head of the chain supports the grapheme, nothing else matters
+    pub const FIRST_FONT: Score = Score(u32::MAX);
+
+    fn new(coverage: u32, locale: u32, variant: u32) -> Self {
+        Score(coverage << 29 | locale << 1 | variant)
+    }
+
+    pub fn is_supported(&self) -> bool {
+        *self != Self::UNSUPPORTED
+    }
+
+    /// 1 for plain support, 2 if the family suits a requested emoji or text presentation
+    pub fn coverage(&self) -> u32 {
+        self.0 >> 29
+    }
+
+    /// How well the family lang matches the requested lang, see [crate::locale]
+    pub fn locale(&self) -> u32 {
+        (self.0 >> 1) & ((1 << 28) - 1)
+    }
+
+    /// 1 if the family variant suits the requested variant
+    pub fn variant(&self) -> u32 {
+        self.0 & 1
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Score::UNSUPPORTED => f.write_str("unsupported"),
+            Score::FIRST_FONT => f.write_str("first font"),
+            _ => write!(
+                f,
+                "coverage {} locale {} variant {}",
+                self.coverage(),
+                self.locale(),
+                self.variant()
+            ),
+        }
+    }
+}
+
+const EMOJI_STYLE_VS: char = '\u{fe0f}';
+const ZWJ: char = '\u{200d}';
+const TEXT_STYLE_VS: char = '\u{fe0e}';
+
+fn is_variation_selector(c: char) -> bool {
+    matches!(c, '\u{fe00}'..='\u{fe0f}' | '\u{e0100}'..='\u{e01ef}')
+}
+
+/// Whether a grapheme starting with base should be color emoji, see Minikin's calcCoverageScore
+///
+/// A presentation selector decides, failing that the emoji style of the request, failing that
+/// the Emoji_Presentation property of base. Regional indicators are Emoji_Presentation but one
+/// that isn't part of a flag is better drawn as a letter.
+fn wants_color(request: &LocaleList, base: char, vs: Option<char>) -> bool {
+    match vs {
+        Some(EMOJI_STYLE_VS) => true,
+        Some(TEXT_STYLE_VS) => false,
+        _ => match request.emoji_style() {
+            EmojiStyle::Emoji => true,
+            EmojiStyle::Text => false,
+            EmojiStyle::Empty | EmojiStyle::Default => {
+                !is_regional_indicator(base) && sets::emoji_presentation().contains(base)
+            }
+        },
+    }
+}
+
+/// Default ignorables are never drawn, see
+/// <https://www.unicode.org/reports/tr44/#Default_Ignorable_Code_Point>
+fn is_default_ignorable(c: char) -> bool {
+    sets::default_ignorable_code_point().contains(c)
+}
+
+/// Tag characters, used in subdivision flags such as 🏴󠁧󠁢󠁳󠁣󠁴󠁿
+fn is_tag(c: char) -> bool {
+    matches!(c, '\u{e0020}'..='\u{e007f}')
+}
+
+/// True if the grapheme is an emoji, in which case ZWJ and tags are meaningful
+fn is_emoji_sequence(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|c| sets::extended_pictographic().contains(c))
+}
+
+/// Whether a family has to support c to support a grapheme containing it
+///
+/// Variation selectors are handled separately, other default ignorables only matter as
+/// part of an emoji sequence, e.g. the ZWJ in 👩‍🚀. Control characters, e.g. newline and tab,
+/// are never drawn.
+fn needs_glyph(c: char, emoji_sequence: bool) -> bool {
+    if is_variation_selector(c) || c.is_control() {
+        false
+    } else if is_default_ignorable(c) {
+        emoji_sequence && (c == ZWJ || is_tag(c))
+    } else {
+        true
+    }
+}
+
+/// The codepoints that must form a single glyph for a family with sequences to support text
+///
+/// Drops variation selectors, anything else that needn't be drawn, and a trailing ZWJ since
+/// 👩‍ by itself is just 👩.
+fn sequence_key(text: &str, emoji_sequence: bool) -> Vec<u32> {
+    let mut key = text
+        .chars()
+        .filter(|c| needs_glyph(*c, emoji_sequence))
+        .map(|c| c as u32)
+        .collect::<Vec<_>>();
+    if key.len() > 1 && key.last() == Some(&(ZWJ as u32)) {
+        key.pop();
+    }
+    key
+}
+
+/// True if family draws text, all of whose codepoints it supports, correctly
+///
+/// A flag needs a family known to have it, anything else only needs a known sequence if the
+/// family has them.
+fn forms_glyph(family: &Family, text: &str, emoji_sequence: bool) -> bool {
+    if family.sequences.is_none() && !text.starts_with(is_regional_indicator) {
+        return true;
+    }
+    let key = sequence_key(text, emoji_sequence);
+    key.len() < 2
+        || family
+            .sequences
+            .as_ref()
+            .is_some_and(|sequences| sequences.contains(&key))
+}
+
+/// The letters, A-Z, that pair up to spell a region code and form a flag
+pub(crate) fn is_regional_indicator(c: char) -> bool {
+    matches!(c, '\u{1f1e6}'..='\u{1f1ff}')
+}
+
+/// The region code, e.g. JP, a regional indicator pair spells, if grapheme is one
+pub(crate) fn flag_region(grapheme: &str) -> Option<SmolStr> {
+    let mut chars = grapheme.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(a), Some(b), None) if is_regional_indicator(a) && is_regional_indicator(b) => Some(
+            [a, b]
+                .into_iter()
+                .map(|c| char::from(b'A' + (c as u32 - 0x1f1e6) as u8))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// Minikin keeps using the previous font for these if it supports them, see isStickyWhitelisted
+fn is_sticky(c: char) -> bool {
+    matches!(
+        c,
+        '!' | ','
+            | '-'
+            | '.'
+            | ':'
+            | ';'
+            | '?'
+            | '\u{00a0}' // NBSP
+            | '\u{2010}' // HYPHEN
+            | '\u{2011}' // NB_HYPHEN
+            | '\u{202f}' // NNBSP
+            | '\u{2640}' // FEMALE_SIGN
+            | '\u{2642}' // MALE_SIGN
+            | '\u{2695}' // STAFF_OF_AESCULAPIUS
+    )
 }
 
 #[derive(Debug)]
-struct CodepointMapping {
-    start: u32,
-    end: u32,
-    font: FontIdx,
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub(crate) struct CodepointMapping {
+    pub(crate) start: u32,
+    pub(crate) end: u32,
+    pub(crate) font: FontIdx,
+}
+
+/// Find the family a sorted, non-overlapping table maps cp to
+fn find_mapping(mappings: &[CodepointMapping], cp: u32) -> Option<FontIdx> {
+    mappings
+        .binary_search_by(|m| match cp {
+            cp if m.start > cp => Ordering::Greater,
+            cp if m.end < cp => Ordering::Less,
+            _ => Ordering::Equal,
+        })
+        .ok()
+        .map(|mapping_idx| mappings[mapping_idx].font)
+}
+
+/// A grapheme, or part of one, and the family chosen for it
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Piece {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) font: Option<FontIdx>,
+    pub(crate) path: MatchPath,
 }
 
+/// Every piece of a text, see [FallbackChain::resolve_text]
+pub(crate) struct Resolved {
+    pub(crate) pieces: Vec<Piece>,
+    pub(crate) missing: MissingCoverage,
+    /// The family [ItemizeOptions::headline] moved pieces to, if any
+    pub(crate) headline: Option<FontIdx>,
+}
+
+/// Merge sorted (codepoint, family) pairs into ranges
+fn to_mappings(sorted: impl IntoIterator<Item = (u32, FontIdx)>) -> Vec<CodepointMapping> {
+    let mut mappings: Vec<CodepointMapping> = Vec::new();
+    for (cp, font) in sorted {
+        match mappings.last_mut() {
+            Some(curr) if curr.end + 1 == cp && curr.font == font => curr.end = cp,
+            _ => mappings.push(CodepointMapping {
+                start: cp,
+                end: cp,
+                font,
+            }),
+        }
+    }
+    mappings
+}
+
+/// The winners of ambiguous codepoints for a request for a single locale, or for no locale
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub(crate) struct LangMappings {
+    /// The family and index into its lang of the locale requested, None if no locale
+    pub(crate) source: Option<(FontIdx, usize)>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) request: LocaleList,
+    // No overlaps. Sorted.
+    pub(crate) mappings: Vec<CodepointMapping>,
+}
+
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FallbackChain {
-    name: SmolStr,
-    families: Vec<Family>,
+    pub(crate) name: SmolStr,
+    pub(crate) families: Vec<Family>,
     // No overlaps. Sorted.
-    mappings: Vec<CodepointMapping>,
+    pub(crate) mappings: Vec<CodepointMapping>,
+    pub(crate) stats: ChainStats,
+    // The parsed lang of each family
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) locales: Vec<LocaleList>,
+    // Jump tables for codepoints where lang decides the winner
+    pub(crate) lang_mappings: Vec<LangMappings>,
+}
+
+/// Statistics about how codepoints are distributed across the families of a [FallbackChain]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ChainStats {
+    /// Codepoints supported by at least one family
+    pub codepoints: usize,
+    /// Codepoints that map to exactly one family, or to the head
+    pub distinct: usize,
+    /// Codepoints that map to multiple families but one is the clear winner due to lack of lang tags
+    pub unambiguous_conflict: usize,
+    /// Distinct groups of families that share codepoints where lang may decide the winner
+    pub conflict_groups: usize,
+    /// Ranges in the table used to jump straight to the family for unambiguous codepoints
+    pub mappings: usize,
+    /// Ranges, across all the per-lang tables, used to jump straight to the family for ambiguous codepoints
+    pub lang_mappings: usize,
+}
+
+/// Parse the lang of each family
+pub(crate) fn family_locales(families: &[Family]) -> Result<Vec<LocaleList>, Error> {
+    families
+        .iter()
+        .map(|f| LocaleList::parse(f.lang.as_deref().unwrap_or_default()))
+        .collect()
 }
 
 impl FallbackChain {
+    /// Build a chain from families in priority order, the first being the head of the chain
+    ///
+    /// Every family must support at least one codepoint.
     pub fn for_fonts(
         name: &str,
         mut families: Vec<Family>,
         codepoints: impl Fn(&Family) -> HashSet<u32>,
-    ) -> Self {
-        let codepoints = families.iter().map(|f| codepoints(f)).collect::<Vec<_>>();
-
-        let font_indices = (0..families.len())
-            .map(|i| (&families[i], FontIdx(i)))
-            .collect::<HashMap<_, _>>();
+    ) -> Result<Self, Error> {
+        if families.is_empty() {
+            return Err(Error::EmptyChain);
+        }
+        let codepoints = families.iter().map(codepoints).collect::<Vec<_>>();
+        for (family, codepoints) in families.iter().zip(codepoints.iter()) {
+            if codepoints.is_empty() {
+                return Err(Error::NoCoverage(family.family_name.clone()));
+            }
+        }
+        let locales = family_locales(&families)?;
 
         // Map each codepoint to the families that support it
-        let mut families_by_cp = HashMap::<u32, HashSet<&Family>>::new();
-        for (font, codepoints) in families.iter().zip(codepoints.iter()) {
+        let mut families_by_cp = HashMap::<u32, BTreeSet<FontIdx>>::new();
+        for (i, codepoints) in codepoints.iter().enumerate() {
             for cp in codepoints {
-                families_by_cp.entry(*cp).or_default().insert(font);
+                families_by_cp.entry(*cp).or_default().insert(FontIdx(i));
             }
         }
 
         // Match Android in preferring the head to all alternatives
         for support_group in families_by_cp.values_mut() {
-            if support_group.contains(&families[0]) {
-                support_group.retain(|f| *f == &families[0]);
+            if support_group.contains(&FontIdx(0)) {
+                support_group.retain(|f| *f == FontIdx(0));
             }
         }
 
         let mut distinct = 0;
         let mut unambiguous_conflict = 0;
-        let mut conflict_groups = HashMap::<Vec<_>, HashSet<u32>>::new();
+        let mut conflict_groups = HashMap::<&BTreeSet<FontIdx>, HashSet<u32>>::new();
         for (cp, fonts) in families_by_cp.iter() {
             if fonts.len() == 1 {
                 distinct += 1;
                 continue;
             }
             // if we don't have lang tags there is no reason to alter priority
-            if !fonts.iter().any(|f| f.lang.is_some()) {
+            if !fonts.iter().any(|f| families[f.0].lang.is_some()) {
                 unambiguous_conflict += 1;
                 continue;
             }
             // Conflicts!
-            let mut conflict: Vec<_> = fonts.into_iter().collect();
-            conflict.sort();
-            conflict_groups.entry(conflict).or_default().insert(*cp);
+            conflict_groups.entry(fonts).or_default().insert(*cp);
         }
 
-        for (families, codepoints) in conflict_groups.iter() {
-            let mut cp_str = codepoints
-                .iter()
-                .map(|cp| format!("0x{cp:04x}"))
-                .collect::<Vec<_>>();
-            cp_str.sort();
-            let cp_str = cp_str.into_iter().join(",");
-            eprintln!(
-                "{} {}",
-                families.iter().map(|f| f.family_name.as_str()).join(","),
-                cp_str
-            );
+        // Formatting every conflicting codepoint is costly, tens of thousands for CJK
+        if tracing::enabled!(tracing::Level::TRACE) {
+            for (fonts, codepoints) in conflict_groups.iter() {
+                let mut cp_str = codepoints
+                    .iter()
+                    .map(|cp| format!("0x{cp:04x}"))
+                    .collect::<Vec<_>>();
+                cp_str.sort();
+                trace!(
+                    families = %fonts
+                        .iter()
+                        .map(|f| families[f.0].family_name.as_str())
+                        .join(","),
+                    codepoints = %cp_str.into_iter().join(","),
+                    "conflict group"
+                );
+            }
         }
 
-        eprintln!(
-            "{distinct}/{} ({:.1}%) codepoints map to exactly 1 family",
-            families_by_cp.len(),
-            100.0 * distinct as f32 / families_by_cp.len() as f32
-        );
-        eprintln!(
-            "{unambiguous_conflict}/{} ({:.1}%) codepoints map to multiple families but one is the clear winner",
-            families_by_cp.len(),
-            100.0 * unambiguous_conflict as f32 / families_by_cp.len() as f32
-        );
-
-        eprintln!(
-            "{} distinct groups of fonts with ambiguous codepoints",
-            conflict_groups.len()
-        );
-
         let mut unambiguous_cp = families_by_cp
             .iter()
-            .filter_map(|(cp, families)| if families.len() == 1 { Some(*cp) } else { None })
+            .filter_map(|(cp, fonts)| match fonts.iter().exactly_one() {
+                Ok(font) => Some((*cp, *font)),
+                Err(_) => None,
+            })
             .collect::<Vec<_>>();
         unambiguous_cp.sort();
         let num_unambiguous = unambiguous_cp.len();
 
-        let font_idx_for_cp = |cp| {
-            *font_indices
-                .get(families_by_cp.get(&cp).unwrap().iter().next().unwrap())
-                .unwrap()
-        };
-        let mut mappings = vec![CodepointMapping {
-            start: unambiguous_cp[0],
-            end: unambiguous_cp[0],
-            font: font_idx_for_cp(unambiguous_cp[0]),
-        }];
-        for cp in unambiguous_cp.into_iter().skip(1) {
-            let font = font_idx_for_cp(cp);
-            let curr = mappings.last_mut().unwrap();
-            if curr.end + 1 == cp && curr.font == font {
-                curr.end = cp;
-            } else {
-                mappings.push(CodepointMapping {
-                    start: cp,
-                    end: cp,
-                    font,
-                })
-            }
-        }
+        let mappings = to_mappings(unambiguous_cp);
 
-        eprintln!(
-            "{} mappings for {} unambiguous cp",
-            mappings.len(),
-            num_unambiguous
-        );
+        let stats = ChainStats {
+            codepoints: families_by_cp.len(),
+            distinct,
+            unambiguous_conflict,
+            conflict_groups: conflict_groups.len(),
+            mappings: mappings.len(),
+            lang_mappings: 0,
+        };
 
-        for (family, codepoints) in families.iter_mut().zip(codepoints.into_iter()) {
+        for (family, codepoints) in families.iter_mut().zip(codepoints) {
             family.codepoints = codepoints.into_iter().collect();
         }
 
-        FallbackChain {
+        let mut chain = FallbackChain {
             name: name.into(),
             families,
             mappings,
+            stats,
+            locales,
+            lang_mappings: Vec::new(),
+        };
+        chain.lang_mappings = chain.build_lang_mappings(&conflict_groups);
+        chain.stats.lang_mappings = chain.lang_mappings.iter().map(|t| t.mappings.len()).sum();
+
+        let stats = &chain.stats;
+        debug!(
+            name,
+            codepoints = stats.codepoints,
+            distinct = stats.distinct,
+            unambiguous_conflict = stats.unambiguous_conflict,
+            conflict_groups = stats.conflict_groups,
+            mappings = stats.mappings,
+            lang_mappings = stats.lang_mappings,
+            lang_tables = chain.lang_mappings.len(),
+            unambiguous = num_unambiguous,
+            "built fallback chain"
+        );
+        Ok(chain)
+    }
+
+    /// The request a per-lang table is for, see [LangMappings::source]
+    pub(crate) fn lang_request(&self, source: Option<(FontIdx, usize)>) -> Option<LocaleList> {
+        match source {
+            None => Some(LocaleList::default()),
+            Some((font, idx)) => self.locales.get(font.0)?.single(idx),
+        }
+    }
+
+    /// Precompute, for no lang and for each distinct locale a family supports, the winner of
+    /// every codepoint in a conflict group
+    ///
+    /// Within a conflict group every family supports every codepoint and the head supports
+    /// none of them so the winner depends only on the family and whether the codepoint is
+    /// emoji presentation by default.
+    fn build_lang_mappings(
+        &self,
+        conflict_groups: &HashMap<&BTreeSet<FontIdx>, HashSet<u32>>,
+    ) -> Vec<LangMappings> {
+        let sources = once(None).chain(
+            self.locales
+                .iter()
+                .enumerate()
+                .flat_map(|(i, l)| (0..l.len()).map(move |idx| Some((FontIdx(i), idx)))),
+        );
+        let mut tables: Vec<LangMappings> = Vec::new();
+        for source in sources {
+            let request = self.lang_request(source).unwrap();
+            if tables.iter().any(|t| t.request == request) {
+                continue;
+            }
+            let mut winners = Vec::new();
+            for (fonts, codepoints) in conflict_groups.iter() {
+                // Whether a codepoint should be color emoji is the only thing that varies by codepoint
+                let winner = |color| {
+                    fonts
+                        .iter()
+                        .copied()
+                        .max_by_key(|f| (self.rank(*f, &request, None, color), Reverse(*f)))
+                        .expect("Conflict groups have multiple families")
+                };
+                let (color_winner, text_winner) = (winner(true), winner(false));
+                winners.extend(codepoints.iter().map(|cp| {
+                    let base = char::from_u32(*cp).unwrap_or_default();
+                    if wants_color(&request, base, None) {
+                        (*cp, color_winner)
+                    } else {
+                        (*cp, text_winner)
+                    }
+                }));
+            }
+            winners.sort();
+            tables.push(LangMappings {
+                source,
+                request,
+                mappings: to_mappings(winners),
+            });
         }
+        tables
+    }
+
+    /// The per-lang table for request, empty if there isn't one
+    pub(crate) fn lang_mappings(&self, request: &LocaleList) -> &[CodepointMapping] {
+        self.lang_mappings
+            .iter()
+            .find(|t| t.request == *request)
+            .map(|t| t.mappings.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The name of the chain, typically the name of the family at its head, e.g. sans-serif
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How codepoints were distributed across families when the chain was built
+    pub fn stats(&self) -> &ChainStats {
+        &self.stats
+    }
+
+    /// The families of the chain, in priority order
+    pub fn families(&self) -> &[Family] {
+        &self.families
     }
 
-    // TODO: match Android, test as much
-    fn score(family: &Family, lang: &str, grapheme: &str) -> i32 {
-        // TODO: handle fe0f properly
-        if grapheme
+    /// Score a family for a grapheme the way Minikin would
+    ///
+    /// Requests are always for the default variant, which like Android prefers compact families.
+    pub(crate) fn score(&self, font: FontIdx, request: &LocaleList, grapheme: &str) -> Score {
+        let family = &self.families[font.0];
+        let emoji_sequence = is_emoji_sequence(grapheme);
+        let mut vs = None;
+        for c in grapheme.chars() {
+            if is_variation_selector(c) {
+                vs = vs.or(Some(c));
+            } else if needs_glyph(c, emoji_sequence) && !family.codepoints.contains(&(c as u32)) {
+                return Score::UNSUPPORTED;
+            }
+        }
+        if !forms_glyph(family, grapheme, emoji_sequence) {
+            return Score::UNSUPPORTED;
+        }
+        let base = grapheme
             .chars()
-            .filter(|cp| (*cp as u32) != 0xfe0f)
-            .any(|cp| !family.codepoints.contains(&(cp as u32)))
-        {
-            return i32::MIN;
+            .find(|c| !is_default_ignorable(*c))
+            .unwrap_or_default();
+        self.rank(font, request, vs, wants_color(request, base, vs))
+    }
+
+    /// Score a family that supports a grapheme
+    ///
+    /// vs is the grapheme's variation selector, if any, and color whether it should be color emoji.
+    fn rank(&self, font: FontIdx, request: &LocaleList, vs: Option<char>, color: bool) -> Score {
+        let family = &self.families[font.0];
+        // We don't know which variation sequences a family supports so we never have a "VS glyph"
+        if vs.is_none() && font.0 == 0 {
+            return Score::FIRST_FONT;
         }
-        let mut score = 0; // full support, no other clues
-        if Some(lang) == family.lang.as_deref() {
-            score = i32::MAX;
+        let supported = &self.locales[font.0];
+        let coverage = if color == family.emoji { 2 } else { 1 };
+        let locale = request.score_for(supported);
+        let variant = match family.variant {
+            Variant::Default | Variant::Compact => 1,
+            Variant::Elegant => 0,
+        };
+        Score::new(coverage, locale, variant)
+    }
+
+    /// Reject text we can't or won't itemize
+    pub(crate) fn check_input(text: &str) -> Result<(), Error> {
+        if text.len() > MAX_TEXT_LEN {
+            return Err(Error::TextTooLarge {
+                len: text.len(),
+                max: MAX_TEXT_LEN,
+            });
         }
-        score
+        Ok(())
     }
 
-    pub fn itemize<'chain, 'text>(
-        &'chain self,
-        text: &'text str,
-        lang: &str,
-        dest: &mut Vec<Run<'chain>>,
-    ) -> Result<(), Error> {
-        dest.clear();
-        for (start, end) in GraphemeClusterSegmenter::new()
+    /// The (start, end) byte offsets of each grapheme in text
+    pub(crate) fn graphemes(text: &str) -> Vec<(usize, usize)> {
+        GraphemeClusterSegmenter::new()
             .segment_str(text)
             .tuple_windows()
+            .collect()
+    }
+
+    /// Pick the family for a single grapheme, returning the path taken to find it
+    ///
+    /// prev is the family of the preceding grapheme, if any. lang_mappings is the per-lang table
+    /// for request, see [FallbackChain::lang_mappings].
+    pub(crate) fn resolve(
+        &self,
+        grapheme: &str,
+        request: &LocaleList,
+        lang_mappings: &[CodepointMapping],
+        prev: Option<FontIdx>,
+    ) -> (Option<FontIdx>, MatchPath) {
+        let emoji_sequence = is_emoji_sequence(grapheme);
+        let mut glyphs = grapheme.chars().filter(|c| needs_glyph(*c, emoji_sequence));
+        let Some(first) = glyphs.next() else {
+            // Nothing to draw, e.g. a lone ZWNJ or a newline, so don't break the run for it
+            if let Some(prev) = prev {
+                return (Some(prev), MatchPath::Sticky);
+            }
+            return self.walk(grapheme, request);
+        };
+        let single_glyph = glyphs.next().is_none();
+        let single_char = grapheme.chars().nth(1).is_none();
+
+        if single_char
+            && is_sticky(first)
+            && let Some(prev) = prev
+            && self.families[prev.0].codepoints.contains(&(first as u32))
         {
-            let grapheme = &text[start..end];
-            let mut chars = grapheme.chars();
-            let mut match_type = "unattempted";
-            let Some(first) = chars.next() else {
-                debug_assert!(false, "empty grapheme?!");
-                continue;
+            return (Some(prev), MatchPath::Sticky);
+        }
+
+        if single_glyph && !grapheme.chars().any(is_variation_selector) {
+            // Only one char to draw, see if exactly one family supports it
+            let font = find_mapping(&self.mappings, first as u32);
+            if font.is_some() {
+                return (font, MatchPath::Jump);
+            }
+            // or if we already know which of several wins for this lang
+            let font = find_mapping(lang_mappings, first as u32);
+            if font.is_some() {
+                return (font, MatchPath::LangJump);
+            }
+        }
+
+        self.walk(grapheme, request)
+    }
+
+    /// Walk the chain to find the best match that supports the entire grapheme
+    fn walk(&self, grapheme: &str, request: &LocaleList) -> (Option<FontIdx>, MatchPath) {
+        let mut winner = FontIdx(0);
+        let mut score = self.score(winner, request, grapheme);
+        for candidate in (1..self.families.len()).map(FontIdx) {
+            if score == Score::FIRST_FONT {
+                // can't beat that
+                break;
+            }
+            let candidate_score = self.score(candidate, request, grapheme);
+            if candidate_score > score {
+                winner = candidate;
+                score = candidate_score;
+            }
+        }
+        if score.is_supported() {
+            (Some(winner), MatchPath::Walk)
+        } else {
+            (None, MatchPath::WalkToEof)
+        }
+    }
+
+    /// Resolve the grapheme at text[start..end], splitting it if no family supports all of it
+    ///
+    /// Pushes one piece, or several for a split grapheme, onto pieces.
+    pub(crate) fn resolve_pieces(
+        &self,
+        text: &str,
+        (start, end): (usize, usize),
+        request: &LocaleList,
+        lang_mappings: &[CodepointMapping],
+        prev: Option<FontIdx>,
+        pieces: &mut Vec<Piece>,
+    ) {
+        let grapheme = &text[start..end];
+        let (font, path) = self.resolve(grapheme, request, lang_mappings, prev);
+        let emoji_sequence = is_emoji_sequence(grapheme);
+        let splittable = grapheme
+            .chars()
+            .filter(|c| needs_glyph(*c, emoji_sequence))
+            .nth(1)
+            .is_some();
+        if font.is_some() || !splittable {
+            pieces.push(Piece {
+                start,
+                end,
+                font,
+                path,
+            });
+            return;
+        }
+
+        // Give the longest prefix any family supports to the best such family, repeat for the rest
+        let mut piece_start = start;
+        while piece_start < end {
+            let rest = &text[piece_start..end];
+            let mut best: Option<(usize, Score, FontIdx)> = None;
+            for font in (0..self.families.len()).map(FontIdx) {
+                let len = self.supported_prefix(font, rest, emoji_sequence);
+                if len == 0 {
+                    continue;
+                }
+                let score = self.score(font, request, &rest[..len]);
+                if best
+                    .is_none_or(|(best_len, best_score, _)| (len, score) > (best_len, best_score))
+                {
+                    best = Some((len, score, font));
+                }
+            }
+            let (len, font) = match best {
+                Some((len, _, font)) => (len, Some(font)),
+                // Nobody supports the next char, it and anything after it that needn't be drawn are missing
+                None => (
+                    rest.char_indices()
+                        .skip(1)
+                        .find(|(_, c)| needs_glyph(*c, emoji_sequence))
+                        .map(|(i, _)| i)
+                        .unwrap_or(rest.len()),
+                    None,
+                ),
             };
+            pieces.push(Piece {
+                start: piece_start,
+                end: piece_start + len,
+                font,
+                path: MatchPath::Partial,
+            });
+            piece_start += len;
+        }
+    }
 
-            let mut family: Option<&'chain Family> = None;
-
-            if chars.next().is_none() {
-                // Single char grapheme, see if exactly one family supports it
-                family = self
-                    .mappings
-                    .binary_search_by(|m| match first as u32 {
-                        first if m.start > first => Ordering::Greater,
-                        first if m.end < first => Ordering::Less,
-                        _ => Ordering::Equal,
-                    })
-                    .ok()
-                    .map(|mapping_idx| &self.families[self.mappings[mapping_idx].font.0]);
-                match_type = "jump";
+    /// The length in bytes of the longest prefix of text the family supports
+    ///
+    /// Chars that needn't be drawn count as supported so they stay with the char they follow.
+    fn supported_prefix(&self, font: FontIdx, text: &str, emoji_sequence: bool) -> usize {
+        let family = &self.families[font.0];
+        // Where the prefix could end, just before each char that needs drawing and at the end
+        let mut ends = Vec::new();
+        let mut len = 0;
+        for (i, c) in text.char_indices() {
+            if needs_glyph(c, emoji_sequence) {
+                if !family.codepoints.contains(&(c as u32)) {
+                    break;
+                }
+                if len > 0 {
+                    ends.push(len);
+                }
             }
+            len = i + c.len_utf8();
+        }
+        if len > 0 {
+            ends.push(len);
+        }
+        // The longest that doesn't need a sequence the family lacks
+        ends.into_iter()
+            .rev()
+            .find(|end| forms_glyph(family, &text[..*end], emoji_sequence))
+            .unwrap_or(0)
+    }
 
-            if family.is_none() {
-                // Walk the chain to find the best match that supports the entire grapheme
-                let mut winner = &self.families[0];
-                let mut score = Self::score(&winner, lang, grapheme);
-                for candidate in self.families.iter().skip(1) {
-                    let candidate_score = Self::score(candidate, lang, grapheme);
-                    if candidate_score > score {
-                        winner = candidate;
-                        score = candidate_score;
-                    }
-                    if score == i32::MAX {
-                        // can't beat that
-                        break;
-                    }
+    /// Move every word whose graphemes landed in more than one family, or none, to the first
+    /// family in the chain that supports all of it, if any does
+    ///
+    /// Words are as ICU word segmentation sees them, so e.g. Thai works too. Words no single
+    /// family supports keep their per grapheme families.
+    fn stabilize_words(&self, text: &str, request: &LocaleList, pieces: &mut Vec<Piece>) {
+        let resolved = std::mem::take(pieces);
+        let mut resolved = resolved.into_iter().peekable();
+        let segmenter = WordSegmenter::new_auto();
+        let mut segments = segmenter.segment_str(text);
+        let mut start = segments.next().unwrap_or_default();
+        while let Some(end) = segments.next() {
+            let word = &text[start..end];
+            let first = pieces.len();
+            while let Some(piece) = resolved.next_if(|p| p.start < end) {
+                pieces.push(piece);
+            }
+            let word_pieces = &pieces[first..];
+            let stable = word_pieces
+                .iter()
+                .all(|p| p.font.is_some() && p.font == word_pieces[0].font);
+            if segments.is_word_like() && !stable {
+                let graphemes = Self::graphemes(word);
+                let font = (0..self.families.len()).map(FontIdx).find(|font| {
+                    graphemes
+                        .iter()
+                        .all(|(s, e)| self.score(*font, request, &word[*s..*e]).is_supported())
+                });
+                if let Some(font) = font {
+                    debug!(word, family = %self.families[font.0].family_name, "promoted word");
+                    pieces.truncate(first);
+                    pieces.extend(graphemes.into_iter().map(|(s, e)| Piece {
+                        start: start + s,
+                        end: start + e,
+                        font: Some(font),
+                        path: MatchPath::Word,
+                    }));
                 }
-                if score > i32::MIN {
-                    family = Some(winner);
-                    match_type = "walk";
-                } else {
-                    match_type = "walk_to_eof";
+            }
+            start = end;
+        }
+        pieces.extend(resolved);
+    }
+
+    /// True if font is as good a choice for grapheme as winner, the family [FallbackChain::resolve]
+    /// picked, as far as presentation and lang go
+    ///
+    /// Anything that suits the presentation will do in place of the head of the chain.
+    fn acceptable(
+        &self,
+        font: FontIdx,
+        request: &LocaleList,
+        grapheme: &str,
+        winner: FontIdx,
+    ) -> bool {
+        if font == winner {
+            return true;
+        }
+        let score = self.score(font, request, grapheme);
+        if !score.is_supported() {
+            return false;
+        }
+        match self.score(winner, request, grapheme) {
+            Score::FIRST_FONT => score.coverage() == 2,
+            best => score.coverage() == best.coverage() && score.locale() == best.locale(),
+        }
+    }
+
+    /// The families each piece could move to, those [FallbackChain::acceptable] for it, in chain
+    /// order
+    ///
+    /// Pieces of split graphemes stay put, pieces without a family have nowhere to go.
+    fn candidates(&self, text: &str, request: &LocaleList, pieces: &[Piece]) -> Vec<Vec<FontIdx>> {
+        pieces
+            .iter()
+            .map(|piece| {
+                let Some(winner) = piece.font else {
+                    return Vec::new();
+                };
+                if piece.path == MatchPath::Partial {
+                    return vec![winner];
+                }
+                let grapheme = &text[piece.start..piece.end];
+                (0..self.families.len())
+                    .map(FontIdx)
+                    .filter(|font| self.acceptable(*font, request, grapheme, winner))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Move every piece the family acceptable for the most pieces can take to it, ties going to
+    /// the earliest in the chain, returning that family
+    fn headline(&self, text: &str, request: &LocaleList, pieces: &mut [Piece]) -> Option<FontIdx> {
+        let candidates = self.candidates(text, request, pieces);
+        let mut counts = vec![0usize; self.families.len()];
+        for font in candidates.iter().flatten() {
+            counts[font.0] += 1;
+        }
+        let best = (0..self.families.len())
+            .map(FontIdx)
+            .filter(|font| counts[font.0] > 0)
+            .max_by_key(|font| (counts[font.0], Reverse(*font)))?;
+        debug!(family = %self.families[best.0].family_name, pieces = counts[best.0], "headline");
+        for (piece, candidates) in pieces.iter_mut().zip(candidates) {
+            if piece.font != Some(best) && candidates.contains(&best) {
+                piece.font = Some(best);
+                piece.path = MatchPath::Headline;
+            }
+        }
+        Some(best)
+    }
+
+    /// Reassign pieces so the text uses as few families as possible, see [FallbackChain::cover]
+    fn minimize_families(&self, text: &str, request: &LocaleList, pieces: &mut [Piece]) {
+        self.cover(text, request, pieces, |_| Price {
+            fixed: 1,
+            per_codepoint: 0,
+        });
+    }
+
+    /// Reassign pieces to minimize the estimated download, see [FallbackChain::cover]
+    ///
+    /// Families without a [Cost] are priced as if they were the largest file possible so they're
+    /// only used when nothing with a cost will do.
+    fn minimize_download(&self, text: &str, request: &LocaleList, pieces: &mut [Piece]) {
+        self.cover(text, request, pieces, |font| {
+            match self.families[font.0].cost {
+                Some(Cost::File(bytes)) => Price {
+                    fixed: bytes.into(),
+                    per_codepoint: 0,
+                },
+                Some(Cost::PerCodepoint(bytes)) => Price {
+                    fixed: 0,
+                    per_codepoint: bytes.into(),
+                },
+                None => Price {
+                    fixed: u32::MAX.into(),
+                    per_codepoint: 0,
+                },
+            }
+        });
+    }
+
+    /// Reassign pieces to a cheap set of families that covers them all, a weighted set cover
+    ///
+    /// Each piece may move to any of its [FallbackChain::candidates]. Families are picked greedily, the one that takes the most uncovered
+    /// pieces for what it would cost to download them first, ties going to the earliest in the
+    /// chain. That is the usual approximation, it need not find the true minimum. Each piece
+    /// then goes to the chosen family that adds least to the cost of its codepoints, preferring
+    /// the family resolve picked, then that of the piece before, then the earliest in the chain.
+    fn cover(
+        &self,
+        text: &str,
+        request: &LocaleList,
+        pieces: &mut [Piece],
+        price: impl Fn(FontIdx) -> Price,
+    ) {
+        let candidates = self.candidates(text, request, pieces);
+        let prices = (0..self.families.len())
+            .map(|i| price(FontIdx(i)))
+            .collect::<Vec<_>>();
+
+        let mut uncovered = (0..pieces.len())
+            .filter(|i| !candidates[*i].is_empty())
+            .collect::<Vec<_>>();
+        let mut chosen = HashSet::new();
+        while !uncovered.is_empty() {
+            let mut counts = vec![0u128; self.families.len()];
+            let mut codepoints = vec![HashSet::new(); self.families.len()];
+            for i in uncovered.iter() {
+                let piece = &pieces[*i];
+                for font in candidates[*i].iter() {
+                    counts[font.0] += 1;
+                    codepoints[font.0].extend(text[piece.start..piece.end].chars());
                 }
             }
+            let cost = |font: FontIdx| {
+                let price = prices[font.0];
+                (price.fixed + price.per_codepoint * codepoints[font.0].len() as u64).max(1) as u128
+            };
+            // Compare count / cost without dividing
+            let Some(best) = (0..self.families.len())
+                .map(FontIdx)
+                .filter(|font| counts[font.0] > 0)
+                .reduce(|best, font| {
+                    if counts[font.0] * cost(best) > counts[best.0] * cost(font) {
+                        font
+                    } else {
+                        best
+                    }
+                })
+            else {
+                break;
+            };
+            debug!(family = %self.families[best.0].family_name, pieces = counts[best.0], cost = cost(best), "cover");
+            chosen.insert(best);
+            uncovered.retain(|i| !candidates[*i].contains(&best));
+        }
+
+        let mut prev = None;
+        for (piece, candidates) in pieces.iter_mut().zip(candidates) {
+            let winner = piece.font;
+            let font = candidates
+                .into_iter()
+                .filter(|font| chosen.contains(font))
+                .min_by_key(|font| {
+                    (
+                        prices[font.0].per_codepoint,
+                        Some(*font) != winner,
+                        Some(*font) != prev,
+                        *font,
+                    )
+                });
+            if font != piece.font {
+                piece.font = font;
+                piece.path = MatchPath::Cover;
+            }
+            prev = piece.font;
+        }
+    }
 
-            if let Some(family) = family {
-                if dest.is_empty() {
+    /// Move each neutral grapheme, one whose script is Common or Inherited such as a space or
+    /// digit, into the family of a neighbor that supports it to avoid fragmenting runs
+    ///
+    /// The neighbor must be [FallbackChain::acceptable], e.g. text presentation won't take an
+    /// emoji asking for color.
+    ///
+    /// The nearest non-neutral before wins over the one after, unless the grapheme has
+    /// Script_Extensions, e.g. ー or a danda, that include only the script of the one after.
+    fn stick_neutrals(&self, text: &str, request: &LocaleList, pieces: &mut [Piece]) {
+        let neighbor = |piece: &Piece| match (piece.font, piece.path) {
+            (Some(font), path) if path != MatchPath::Partial => {
+                Some((font, base_script(&text[piece.start..piece.end])))
+            }
+            _ => None,
+        };
+        let is_neutral_piece =
+            |piece: &Piece| neighbor(piece).is_some_and(|(_, script)| is_neutral(script));
+
+        let mut before = None;
+        let mut after = None;
+        let mut after_idx = 0;
+        for idx in 0..pieces.len() {
+            if !is_neutral_piece(&pieces[idx]) {
+                before = neighbor(&pieces[idx]);
+                continue;
+            }
+            if after_idx <= idx {
+                after_idx = (idx + 1..pieces.len())
+                    .find(|i| !is_neutral_piece(&pieces[*i]))
+                    .unwrap_or(pieces.len());
+                after = pieces.get(after_idx).and_then(neighbor);
+            }
+
+            let piece = &mut pieces[idx];
+            let grapheme = &text[piece.start..piece.end];
+            let winner = piece.font.expect("Neutral pieces have a family");
+            let supports = |(font, _): &&(FontIdx, icu_properties::Script)| {
+                self.acceptable(*font, request, grapheme, winner)
+            };
+            let neighbors = [before, after];
+            let Some((font, _)) = neighbors
+                .iter()
+                .flatten()
+                .filter(supports)
+                .find(|(_, script)| extends_to(grapheme, *script))
+                .or_else(|| neighbors.iter().flatten().find(supports))
+            else {
+                continue;
+            };
+            if piece.font != Some(*font) {
+                piece.font = Some(*font);
+                piece.path = MatchPath::Sticky;
+            }
+        }
+    }
+
+    /// Resolve each grapheme of text to pieces, then move them about as options ask
+    ///
+    /// drawn is text as it will be drawn, e.g. with mirrored brackets, the same length as text.
+    pub(crate) fn resolve_text(
+        &self,
+        text: &str,
+        drawn: &str,
+        request: &LocaleList,
+        options: &ItemizeOptions,
+    ) -> Resolved {
+        let lang_mappings = self.lang_mappings(request);
+        let mut prev = None;
+        let mut missing = MissingCoverage::default();
+        let mut pieces = Vec::new();
+        for (start, end) in Self::graphemes(text) {
+            let first = pieces.len();
+            self.resolve_pieces(
+                drawn,
+                (start, end),
+                request,
+                lang_mappings,
+                prev,
+                &mut pieces,
+            );
+            let grapheme_pieces = &pieces[first..];
+            if grapheme_pieces.iter().any(|p| p.font.is_none()) {
+                missing.graphemes += 1;
+            }
+            if grapheme_pieces.len() > 1
+                && let Some(region) = flag_region(&text[start..end])
+            {
+                debug!(region = region.as_str(), "no family draws flag");
+                missing.flags.insert(region);
+            }
+            prev = pieces.last().and_then(|p| p.font);
+        }
+        if options.stable_words {
+            self.stabilize_words(drawn, request, &mut pieces);
+        }
+        let mut headline = None;
+        if options.headline {
+            headline = self.headline(drawn, request, &mut pieces);
+        } else if options.minimize_families {
+            self.minimize_families(drawn, request, &mut pieces);
+        } else if options.minimize_download {
+            self.minimize_download(drawn, request, &mut pieces);
+        }
+        if options.sticky_neutrals {
+            self.stick_neutrals(drawn, request, &mut pieces);
+        }
+        Resolved {
+            pieces,
+            missing,
+            headline,
+        }
+    }
+
+    /// Split text into runs, each in the family that should render it
+    ///
+    /// lang is a comma separated list of BCP 47 tags, most preferred first, see [LocaleList::parse].
+    /// A grapheme no family fully supports is split so the family that supports the longest
+    /// part of it gets that part, see [MatchPath::Partial]. What no family supports ends up in
+    /// runs without a family, summarized by the result.
+    pub fn itemize<'chain>(
+        &'chain self,
+        text: &str,
+        lang: &str,
+        dest: &mut Vec<Run<'chain>>,
+    ) -> Result<MissingCoverage, Error> {
+        self.itemize_locales(text, &LocaleList::parse(lang)?, dest)
+    }
+
+    /// Like [FallbackChain::itemize] for an already parsed list of preferred locales
+    ///
+    /// Families are scored by how well they match each of the first [crate::locale::FONT_LOCALE_LIMIT]
+    /// locales, a better match for an earlier locale beating any match for a later one.
+    pub fn itemize_locales<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+        dest: &mut Vec<Run<'chain>>,
+    ) -> Result<MissingCoverage, Error> {
+        self.itemize_with_options(text, request, &ItemizeOptions::default(), dest)
+    }
+
+    /// Itemize text meant to be set in one family, e.g. a heading or logo, reporting how much of
+    /// it the family covers
+    ///
+    /// The family acceptable, as far as lang and presentation go, for the most graphemes gets
+    /// all of them, the rest fall back as usual. Other options apply as they do for
+    /// [FallbackChain::itemize_with_options], [ItemizeOptions::headline] being implied.
+    pub fn itemize_headline<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+        options: &ItemizeOptions,
+        dest: &mut Vec<Run<'chain>>,
+    ) -> Result<Headline<'chain>, Error> {
+        let options = ItemizeOptions {
+            headline: true,
+            ..options.clone()
+        };
+        let resolved = self.itemize_resolved(text, request, &options, dest)?;
+        let graphemes = Self::graphemes(text);
+        // A grapheme is covered if every piece of it is in the family
+        let mut pieces = resolved.pieces.iter().peekable();
+        let covered = graphemes
+            .iter()
+            .filter(|(_, end)| {
+                let mut covered = true;
+                while let Some(piece) = pieces.next_if(|p| p.start < *end) {
+                    covered &= piece.font.is_some() && piece.font == resolved.headline;
+                }
+                covered
+            })
+            .count();
+        Ok(Headline {
+            family: resolved.headline.map(|font| &self.families[font.0]),
+            covered,
+            total: graphemes.len(),
+            missing: resolved.missing,
+        })
+    }
+
+    /// Like [FallbackChain::itemize_locales] with control over how runs are formed
+    ///
+    /// With [ItemizeOptions::script_runs] a run ends wherever either the family or the script
+    /// changes, with [ItemizeOptions::bidi] wherever the bidi level changes. Neither splits a
+    /// grapheme: each piece of a grapheme takes the script and level in effect where it starts.
+    pub fn itemize_with_options<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+        options: &ItemizeOptions,
+        dest: &mut Vec<Run<'chain>>,
+    ) -> Result<MissingCoverage, Error> {
+        self.itemize_resolved(text, request, options, dest)
+            .map(|resolved| resolved.missing)
+    }
+
+    /// Like [FallbackChain::itemize_with_options] but returns the pieces runs were made of too
+    fn itemize_resolved<'chain>(
+        &'chain self,
+        text: &str,
+        request: &LocaleList,
+        options: &ItemizeOptions,
+        dest: &mut Vec<Run<'chain>>,
+    ) -> Result<Resolved, Error> {
+        dest.clear();
+        Self::check_input(text)?;
+        let scripts = if options.script_runs {
+            script_runs(text)
+                .into_iter()
+                .map(|(start, end, script)| (start, end, script_code(script)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut scripts = scripts.into_iter().peekable();
+        let levels = if options.bidi {
+            bidi::levels(text)
+        } else {
+            Vec::new()
+        };
+        // Resolve what will actually be drawn, mirrors and all
+        let drawn = options
+            .bidi
+            .then(|| bidi::mirrored(text, &levels))
+            .flatten();
+        let drawn = drawn.as_deref().unwrap_or(text);
+        let mut resolved = self.resolve_text(text, drawn, request, options);
+        let missing = &mut resolved.missing;
+
+        for piece in resolved.pieces.iter() {
+            let (start, end) = (piece.start, piece.end);
+            let grapheme = &text[start..end];
+            let match_type = piece.path;
+
+            let family = piece.font.map(|font| &self.families[font.0]);
+            while scripts.next_if(|(_, end, _)| *end <= start).is_some() {}
+            let script = scripts.peek().map(|(_, _, script)| *script);
+            let level = levels.get(start).copied();
+            if family.is_none() {
+                missing.codepoints.extend(
+                    grapheme
+                        .chars()
+                        .filter(|c| !is_default_ignorable(*c) && !c.is_control())
+                        .map(|c| c as u32),
+                );
+                debug!(
+                    grapheme,
+                    %match_type,
+                    codepoints = grapheme.chars().count(),
+                    "no family supports grapheme"
+                );
+            }
+
+            let op;
+            match dest.last_mut() {
+                Some(curr)
+                    if same_family(curr.family, family)
+                        && curr.script == script
+                        && curr.level == level
+                        && curr.end == start =>
+                {
+                    curr.end = end;
+                    op = "continue";
+                }
+                _ => {
                     dest.push(Run {
                         family,
                         start,
-                        end: start,
+                        end,
+                        script,
+                        level,
+                        direction: level.map(Direction::of_level),
                     });
-                }
-
-                let mut curr = dest.last_mut().unwrap();
-                let mut op = "continue";
-                if curr.family == family && curr.end == start {
-                    curr.end = end;
-                } else {
-                    dest.push(Run { family, start, end });
-                    curr = dest.last_mut().unwrap();
                     op = "insert";
                 }
-                eprintln!(
-                    "{grapheme} {match_type} => {} ({}..{}) {} ({op})",
-                    &text[curr.start..curr.end],
-                    curr.start,
-                    curr.end,
-                    curr.family.family_name
-                );
-            } else {
-                eprintln!(
-                    "{grapheme} ({} codepoints) {match_type}, failed",
-                    grapheme.chars().count()
-                );
             }
+            let curr = dest.last().unwrap();
+            trace!(
+                grapheme,
+                %match_type,
+                op,
+                run = &text[curr.start..curr.end],
+                start = curr.start,
+                end = curr.end,
+                family = curr.family.map(|f| f.family_name.as_str()).unwrap_or("missing"),
+            );
         }
-        Ok(())
+        Ok(resolved)
+    }
+}
+
+/// The ISO 15924 code, e.g. Latn, for a script property value
+fn script_code(script: icu_properties::Script) -> Script {
+    icu_properties::Script::enum_to_short_name_mapper()
+        .get(script)
+        .and_then(|code| Script::try_from_bytes(code.as_bytes()).ok())
+        .unwrap_or(script!("Zyyy"))
+}
+
+fn same_family(a: Option<&Family>, b: Option<&Family>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => ptr::eq(a, b),
+        (None, None) => true,
+        _ => false,
     }
 }
@@ -1,15 +1,152 @@
 //! Exploratory hackery
 
+pub(crate) mod bidi;
+pub mod chain_file;
+pub mod css2;
 pub(crate) mod error;
+pub mod explain;
 pub mod fallback_chain;
+pub mod locale;
+pub mod planner;
+pub(crate) mod script;
 
 use fallback_chain::Family;
 
+use std::collections::BTreeSet;
+
+use icu_locid::subtags::Script;
+use smol_str::SmolStr;
+
 pub use crate::error::Error;
 
 #[derive(Debug, Clone)]
 pub struct Run<'a> {
-    pub family: &'a Family,
+    /// None if no family supports the text of the run
+    pub family: Option<&'a Family>,
     pub start: usize,
     pub end: usize,
+    /// The Unicode script of the run, e.g. Latn, if [ItemizeOptions::script_runs] was set
+    ///
+    /// Zyyy, Common, if nothing in the run has a script, e.g. digits and punctuation alone.
+    pub script: Option<Script>,
+    /// The bidi embedding level of the run, even being left to right, if [ItemizeOptions::bidi]
+    /// was set
+    pub level: Option<u8>,
+    /// The direction the run's level implies, if [ItemizeOptions::bidi] was set
+    pub direction: Option<Direction>,
+}
+
+impl Run<'_> {
+    /// The indices of the unicode-range slices of our family that drawing our part of text
+    /// downloads, see [Family::slices_for]
+    pub fn slices(&self, text: &str) -> Option<BTreeSet<usize>> {
+        self.family?.slices_for(text[self.start..self.end].chars())
+    }
+}
+
+/// The direction a bidi level runs in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// Odd levels are right to left
+    pub fn of_level(level: u8) -> Self {
+        if level.is_multiple_of(2) {
+            Direction::Ltr
+        } else {
+            Direction::Rtl
+        }
+    }
+}
+
+/// Optional itemization behaviour, the default being plain font runs
+#[derive(Debug, Clone, Default)]
+pub struct ItemizeOptions {
+    /// Also split runs where the Unicode script changes, even within a family, so each run is
+    /// ready to hand to a shaper, see [Run::script]
+    pub script_runs: bool,
+    /// Run the Unicode Bidi Algorithm over the text and split runs where the embedding level
+    /// changes, see [Run::level]
+    ///
+    /// Each paragraph takes its direction from its first strong character. Mirrored characters
+    /// at right to left levels, e.g. (, need a family that supports their mirror, e.g. ).
+    pub bidi: bool,
+    /// Keep neutral graphemes, e.g. spaces, punctuation and digits, in the family of the run
+    /// before, or failing that after, them if it supports them
+    ///
+    /// Without this "世界 世界" is three runs, the space going to the head of the chain.
+    pub sticky_neutrals: bool,
+    /// Keep each word in a single family, the first in the chain that supports all of it, if
+    /// the families of its graphemes would otherwise differ
+    ///
+    /// Avoids e.g. a Vietnamese word with one letter the head lacks rendering in two typefaces.
+    pub stable_words: bool,
+    /// Use as few families as possible for the whole text, e.g. one CJK family for both the
+    /// Latin and the Han of "abc 世界" rather than the head and a CJK family
+    ///
+    /// Each extra family is an extra request for a subset. Lang and presentation still decide
+    /// which families will do for a grapheme, this only chooses among those. Takes precedence
+    /// over [ItemizeOptions::stable_words].
+    pub minimize_families: bool,
+    /// Choose families to minimize the estimated download for the text, per each family's
+    /// [fallback_chain::Cost], e.g. a small symbol family rather than a large CJK one for a "·"
+    ///
+    /// As with [ItemizeOptions::minimize_families], which wins if both are set, lang and
+    /// presentation still decide which families will do for a grapheme.
+    pub minimize_download: bool,
+    /// Give everything the one family acceptable for the most graphemes can take to it, only
+    /// the rest falling back, e.g. to keep a heading in a single family where possible
+    ///
+    /// Takes precedence over [ItemizeOptions::minimize_families] and
+    /// [ItemizeOptions::minimize_download]. See [fallback_chain::FallbackChain::itemize_headline].
+    pub headline: bool,
+}
+
+/// How much of a headline its family covers, see [fallback_chain::FallbackChain::itemize_headline]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headline<'a> {
+    /// The family acceptable for the most of the text, None if no family supports any of it
+    pub family: Option<&'a Family>,
+    /// Graphemes drawn entirely in the family
+    pub covered: usize,
+    /// Graphemes in the text
+    pub total: usize,
+    pub missing: MissingCoverage,
+}
+
+impl Headline<'_> {
+    /// The share of the graphemes in the family, 1.0 for empty text
+    pub fn share(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.covered as f64 / self.total as f64
+    }
+
+    /// True if some of the text needs another family, or none supports it
+    pub fn is_mixed(&self) -> bool {
+        self.covered < self.total
+    }
+}
+
+/// What an itemization couldn't find a family for, i.e. what will render as tofu
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MissingCoverage {
+    /// Graphemes no family supports, in whole or in part
+    pub graphemes: usize,
+    /// Every codepoint, other than default ignorables, in runs without a family
+    pub codepoints: BTreeSet<u32>,
+    /// The region codes, e.g. JP, of flags no family draws, their regional indicators having
+    /// been drawn as letters if possible
+    pub flags: BTreeSet<SmolStr>,
+}
+
+impl MissingCoverage {
+    /// True if every grapheme found a family and every flag was drawn
+    pub fn is_empty(&self) -> bool {
+        self.graphemes == 0 && self.flags.is_empty()
+    }
 }
@@ -0,0 +1,226 @@
+//! Locale lists and matching modelled on Android's Minikin, see
+//! <https://cs.android.com/android/platform/superproject/main/+/main:frameworks/minikin/libs/minikin/Locale.cpp>
+
+use std::str::FromStr;
+
+use icu_locid::{
+    extensions::unicode::key,
+    subtags::{Language, Script},
+};
+use icu_locid_transform::LocaleExpander;
+use itertools::Itertools;
+
+use crate::Error;
+
+/// Minikin compares at most this many requested locales
+pub const FONT_LOCALE_LIMIT: usize = 3;
+
+// Minikin's subscript bits, so e.g. Jpan can match Hani, Hira, and Kana
+const BOPOMOFO: u8 = 1 << 0;
+const HAN: u8 = 1 << 1;
+const HANGUL: u8 = 1 << 2;
+const HIRAGANA: u8 = 1 << 3;
+const KATAKANA: u8 = 1 << 4;
+const SIMPLIFIED_CHINESE: u8 = 1 << 5;
+const TRADITIONAL_CHINESE: u8 = 1 << 6;
+const EMOJI: u8 = 1 << 7;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) enum EmojiStyle {
+    #[default]
+    Empty,
+    Default,
+    Emoji,
+    Text,
+}
+
+/// A single parsed and maximized BCP 47 tag, reduced to what font selection cares about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Locale {
+    language: Language,
+    script: Option<Script>,
+    sub_script_bits: u8,
+    emoji_style: EmojiStyle,
+}
+
+fn sub_script_bits(script: &str) -> u8 {
+    match script {
+        "Bopo" => BOPOMOFO,
+        "Hang" => HANGUL,
+        "Hanb" => HAN | BOPOMOFO,
+        "Hani" => HAN,
+        "Hans" => HAN | SIMPLIFIED_CHINESE,
+        "Hant" => HAN | TRADITIONAL_CHINESE,
+        "Hira" => HIRAGANA,
+        "Hrkt" => KATAKANA | HIRAGANA,
+        "Jpan" => HAN | KATAKANA | HIRAGANA,
+        "Kana" => KATAKANA,
+        "Kore" => HAN | HANGUL,
+        "Zsye" => EMOJI,
+        _ => 0,
+    }
+}
+
+/// True if every requested subscript is provided
+fn supports_script(provided: u8, requested: u8) -> bool {
+    requested != 0 && (provided & requested) == requested
+}
+
+impl Locale {
+    /// Parse a BCP 47 tag, maximizing it with likely subtags the way Minikin does
+    ///
+    /// Like Minikin we leave und and und-* alone, maximizing und-Zsye would give en-Zsye-US.
+    fn parse(tag: &str) -> Result<Self, Error> {
+        let mut parsed = icu_locid::Locale::try_from_bytes(tag.as_bytes())
+            .map_err(|_| Error::InvalidLanguageTag(tag.into()))?;
+        if !parsed.id.language.is_empty() {
+            // Fills in likely subtags, e.g. ja => ja-Jpan-JP, zh-TW => zh-Hant-TW
+            LocaleExpander::new_extended().maximize(&mut parsed.id);
+        }
+
+        let script = parsed.id.script;
+        let emoji_style = match parsed
+            .extensions
+            .unicode
+            .keywords
+            .get(&key!("em"))
+            .map(|v| v.to_string())
+            .as_deref()
+        {
+            Some("emoji") => EmojiStyle::Emoji,
+            Some("text") => EmojiStyle::Text,
+            Some("default") => EmojiStyle::Default,
+            _ => match script.as_ref().map(Script::as_str) {
+                Some("Zsye") => EmojiStyle::Emoji,
+                Some("Zsym") => EmojiStyle::Text,
+                _ => EmojiStyle::Empty,
+            },
+        };
+        Ok(Locale {
+            language: parsed.id.language,
+            script,
+            sub_script_bits: script
+                .map(|s| sub_script_bits(s.as_str()))
+                .unwrap_or_default(),
+            emoji_style,
+        })
+    }
+
+    /// Minikin's Locale::calcScoreFor, how well this requested locale is served by supported
+    ///
+    /// 5 is language and script match, 4 language and emoji subtag, 3 all supported locales share our
+    /// language and jointly cover our script, 2 emoji subtag, 1 script, 0 nothing.
+    fn score_for(&self, supported: &LocaleList) -> u32 {
+        let mut language_script_match = false;
+        let mut subtag_match = false;
+        let mut script_match = false;
+
+        for other in supported.0.iter() {
+            if self.emoji_style != EmojiStyle::Empty && self.emoji_style == other.emoji_style {
+                subtag_match = true;
+                if self.language == other.language {
+                    return 4;
+                }
+            }
+            if self.script == other.script
+                || supports_script(other.sub_script_bits, self.sub_script_bits)
+            {
+                script_match = true;
+                if self.language == other.language {
+                    language_script_match = true;
+                }
+            }
+        }
+
+        let union = supported.0.iter().fold(0, |acc, l| acc | l.sub_script_bits);
+        if supports_script(union, self.sub_script_bits) {
+            script_match = true;
+            if supported
+                .0
+                .first()
+                .is_some_and(|first| first.language == self.language)
+                && supported.0.iter().map(|l| &l.language).all_equal()
+            {
+                return 3;
+            }
+        }
+
+        if language_script_match {
+            5
+        } else if subtag_match {
+            2
+        } else if script_match {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// An ordered list of locales, most preferred first
+///
+/// Either the langs a family supports, e.g. from fonts.xml lang="zh-Hant,zh-Bopo", or the langs
+/// a reader prefers, e.g. from Accept-Language or an Android LocaleList.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LocaleList(Vec<Locale>);
+
+impl LocaleList {
+    /// Parse a comma separated list of BCP 47 tags, the empty string being the empty list
+    ///
+    /// Whitespace around tags is ignored, as are Accept-Language weights, e.g. the ;q=0.9 of
+    /// "ja, ko;q=0.9". Tags keep their order, not being sorted by weight.
+    pub fn parse(tags: &str) -> Result<Self, Error> {
+        if tags.trim().is_empty() {
+            return Ok(Self::default());
+        }
+        tags.split(',')
+            .map(|tag| {
+                let (tag, _weight) = tag.split_once(';').unwrap_or((tag, ""));
+                Locale::parse(tag.trim())
+            })
+            .collect::<Result<_, _>>()
+            .map(LocaleList)
+            .map_err(|_| Error::InvalidLanguageTag(tags.into()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// A list of just the locale at idx
+    pub(crate) fn single(&self, idx: usize) -> Option<LocaleList> {
+        self.0.get(idx).map(|l| LocaleList(vec![l.clone()]))
+    }
+
+    /// The emoji style of the first locale that has one, e.g. Emoji for und-Zsye or en-u-em-emoji
+    pub(crate) fn emoji_style(&self) -> EmojiStyle {
+        self.0
+            .iter()
+            .map(|l| l.emoji_style)
+            .find(|s| *s != EmojiStyle::Empty)
+            .unwrap_or_default()
+    }
+
+    /// Minikin's calcLocaleMatchingScore, how well a family supporting `supported` serves this list
+    ///
+    /// Earlier locales dominate: each of the first [FONT_LOCALE_LIMIT] contributes a base 6 digit,
+    /// later locales are ignored.
+    pub(crate) fn score_for(&self, supported: &LocaleList) -> u32 {
+        self.0
+            .iter()
+            .take(FONT_LOCALE_LIMIT)
+            .fold(0, |acc, l| acc * 6 + l.score_for(supported))
+    }
+}
+
+impl FromStr for LocaleList {
+    type Err = Error;
+
+    fn from_str(tags: &str) -> Result<Self, Self::Err> {
+        Self::parse(tags)
+    }
+}
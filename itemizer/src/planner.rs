@@ -0,0 +1,389 @@
+//! Plan the subset requests for a whole site, trading requests shared by every page, which the
+//! browser caches, against requests for just what each page needs
+
+use std::collections::BTreeSet;
+
+use smol_str::SmolStr;
+
+use crate::{
+    Error, ItemizeOptions, MissingCoverage,
+    css2::{Css2Options, FamilyText, family_texts, family_url},
+    fallback_chain::{Cost, FallbackChain, Family, Slice},
+    locale::LocaleList,
+};
+
+/// What we assume a codepoint costs for a family without a [Cost]
+pub const UNKNOWN_BYTES_PER_CODEPOINT: u64 = 100;
+
+/// A page of a site
+#[derive(Debug, Clone)]
+pub struct Page {
+    /// Identifies the page in the plan, e.g. its path
+    pub name: String,
+    pub text: String,
+    pub lang: LocaleList,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanOptions {
+    /// How a page is itemized
+    pub itemize: ItemizeOptions,
+    /// How many pages, that use a family, a typical visit views
+    pub pages_per_visit: usize,
+    /// The share of the pages using a family that must use a character for
+    /// [Strategy::Hybrid] to put it in the shared subset
+    pub shared_min_share: f64,
+    /// The chance a visitor already has a slice cached, e.g. from a visit before the site's text
+    /// changed, which requests for just our text don't survive
+    ///
+    /// A slice has every codepoint of its range so, at 0, slices never beat requests for just
+    /// our text.
+    pub slice_hit_rate: f64,
+}
+
+impl Default for PlanOptions {
+    fn default() -> Self {
+        Self {
+            itemize: ItemizeOptions::default(),
+            pages_per_visit: 3,
+            shared_min_share: 0.5,
+            slice_hit_rate: 0.0,
+        }
+    }
+}
+
+/// How to split the characters a family draws across a site into requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Strategy {
+    /// One subset of every character any page needs, shared by all pages
+    SiteWide,
+    /// A shared subset of the characters most pages need, plus one per page for the rest
+    Hybrid,
+    /// A subset per page of just what it needs, nothing shared
+    PageSpecific,
+    /// The whole family, the browser downloading just the [Family::slices] each page uses,
+    /// only for families whose slices we know
+    Slices,
+}
+
+impl Strategy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Strategy::SiteWide => "site-wide",
+            Strategy::Hybrid => "hybrid",
+            Strategy::PageSpecific => "page-specific",
+            Strategy::Slices => "slices",
+        }
+    }
+
+    /// Which of equally cheap strategies to prefer, lowest first
+    ///
+    /// Simpler plans win, and slices must beat requests made for our text to be worth it.
+    fn rank(&self) -> u8 {
+        match self {
+            Strategy::SiteWide => 0,
+            Strategy::PageSpecific => 1,
+            Strategy::Hybrid => 2,
+            Strategy::Slices => 3,
+        }
+    }
+}
+
+/// The estimated download for one family under one strategy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Estimate {
+    pub strategy: Strategy,
+    /// Bytes for a visit of [PlanOptions::pages_per_visit] typical pages, the shared subset, or
+    /// the slices most pages use, being downloaded once
+    pub per_visit: u64,
+    /// Bytes each page, by index, downloads with an empty cache, 0 if it doesn't use the family
+    pub cold: Vec<u64>,
+    /// Bytes each page, by index, downloads with the shared subset, or the slices most pages
+    /// use, already cached
+    pub warm: Vec<u64>,
+}
+
+/// The requests for one family
+#[derive(Debug, Clone)]
+pub struct FamilyPlan<'a> {
+    pub family: &'a Family,
+    /// The strategy with the cheapest [Estimate::per_visit], ties going to the simpler plans,
+    /// site-wide then page-specific
+    pub strategy: Strategy,
+    /// The characters of the subset every page using the family requests, empty for
+    /// [Strategy::Slices]
+    pub shared: BTreeSet<char>,
+    /// The characters each page, by index, requests on top of the shared subset, empty for
+    /// [Strategy::Slices]
+    pub pages: Vec<BTreeSet<char>>,
+    /// The indices of the [Family::slices] each page, by index, uses, empty if we don't know
+    /// the family's slices
+    pub slices: Vec<BTreeSet<usize>>,
+    /// True for each page, by index, that draws anything in the family
+    pub used: Vec<bool>,
+    /// Every grapheme of more than one character any page draws in the family, see
+    /// [FamilyText::clusters]
+    pub clusters: BTreeSet<SmolStr>,
+    /// Every strategy considered
+    pub estimates: Vec<Estimate>,
+}
+
+impl FamilyPlan<'_> {
+    /// What strategy would cost, None if it's not an option for the family
+    pub fn estimate(&self, strategy: Strategy) -> Option<&Estimate> {
+        self.estimates.iter().find(|e| e.strategy == strategy)
+    }
+
+    /// The requests every page using the family makes
+    pub fn shared_urls(&self, options: &Css2Options) -> Vec<String> {
+        if self.strategy == Strategy::Slices {
+            return vec![family_url(self.family, options)];
+        }
+        FamilyText {
+            family: self.family,
+            chars: self.shared.clone(),
+            clusters: self.clusters.clone(),
+        }
+        .urls(options)
+    }
+
+    /// The requests only the page at idx makes
+    pub fn page_urls(&self, idx: usize, options: &Css2Options) -> Vec<String> {
+        FamilyText {
+            family: self.family,
+            chars: self.pages[idx].clone(),
+            clusters: self.clusters.clone(),
+        }
+        .urls(options)
+    }
+}
+
+/// Requests for every family a site uses
+#[derive(Debug, Clone)]
+pub struct SitePlan<'a> {
+    /// In the order families first appear in the pages
+    pub families: Vec<FamilyPlan<'a>>,
+    /// What each page, by index, couldn't find a family for
+    pub missing: Vec<MissingCoverage>,
+}
+
+impl SitePlan<'_> {
+    /// Every request the page at idx makes, shared ones first
+    pub fn page_urls(&self, idx: usize, options: &Css2Options) -> Vec<String> {
+        self.families
+            .iter()
+            .filter(|f| f.used[idx])
+            .flat_map(|f| {
+                let mut urls = f.shared_urls(options);
+                urls.extend(f.page_urls(idx, options));
+                urls
+            })
+            .collect()
+    }
+}
+
+/// What downloading chars of family costs
+fn bytes(family: &Family, chars: usize) -> u64 {
+    if chars == 0 {
+        return 0;
+    }
+    match family.cost {
+        Some(Cost::File(bytes)) => bytes.into(),
+        Some(Cost::PerCodepoint(bytes)) => u64::from(bytes) * chars as u64,
+        None => UNKNOWN_BYTES_PER_CODEPOINT * chars as u64,
+    }
+}
+
+/// What downloading slice of family costs, its share of the family's codepoints
+fn slice_bytes(family: &Family, slice: &Slice) -> u64 {
+    let chars = slice
+        .ranges
+        .iter()
+        .map(|(start, end)| family.codepoints.range(start..=end).count() as u64)
+        .sum::<u64>();
+    match family.cost {
+        Some(Cost::File(bytes)) => u64::from(bytes) * chars / family.codepoints.len().max(1) as u64,
+        Some(Cost::PerCodepoint(bytes)) => u64::from(bytes) * chars,
+        None => UNKNOWN_BYTES_PER_CODEPOINT * chars,
+    }
+}
+
+/// What the pages of a site draw in one family
+struct FamilyUse<'a> {
+    family: &'a Family,
+    /// The characters of each page, by index
+    by_page: Vec<BTreeSet<char>>,
+    /// See [FamilyText::clusters]
+    clusters: BTreeSet<SmolStr>,
+}
+
+impl FallbackChain {
+    /// Itemize every page, then plan the requests for each family to serve a typical visit with
+    /// the fewest bytes
+    ///
+    /// A visit is [PlanOptions::pages_per_visit] of the pages using a family, each as many
+    /// characters as the average such page. Sharing a subset costs its characters once a visit,
+    /// page specific subsets cost theirs on every page.
+    pub fn plan<'chain>(
+        &'chain self,
+        pages: &[Page],
+        options: &PlanOptions,
+    ) -> Result<SitePlan<'chain>, Error> {
+        let mut missing = Vec::with_capacity(pages.len());
+        let mut uses: Vec<FamilyUse> = Vec::new();
+        let mut runs = Vec::new();
+        for (idx, page) in pages.iter().enumerate() {
+            missing.push(self.itemize_with_options(
+                &page.text,
+                &page.lang,
+                &options.itemize,
+                &mut runs,
+            )?);
+            for ft in family_texts(&page.text, &runs) {
+                let i = match uses.iter().position(|u| std::ptr::eq(u.family, ft.family)) {
+                    Some(i) => i,
+                    None => {
+                        uses.push(FamilyUse {
+                            family: ft.family,
+                            by_page: vec![BTreeSet::new(); pages.len()],
+                            clusters: BTreeSet::new(),
+                        });
+                        uses.len() - 1
+                    }
+                };
+                uses[i].by_page[idx] = ft.chars;
+                uses[i].clusters.extend(ft.clusters);
+            }
+        }
+
+        let families = uses
+            .into_iter()
+            .map(|u| plan_family(u.family, u.by_page, u.clusters, options))
+            .collect();
+        Ok(SitePlan { families, missing })
+    }
+}
+
+/// The units, characters or slices, that at least share of the pages using any use
+fn common_units<T: Ord + Copy>(by_page: &[BTreeSet<T>], share: f64) -> BTreeSet<T> {
+    let using = by_page.iter().filter(|units| !units.is_empty()).count();
+    by_page
+        .iter()
+        .flatten()
+        .copied()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|unit| {
+            let pages = by_page.iter().filter(|units| units.contains(unit)).count();
+            pages as f64 >= share * using as f64
+        })
+        .collect()
+}
+
+/// Estimate each page downloading the units, characters or slices, it uses, shared ones being
+/// downloaded once and then cached
+fn estimate<T: Ord + Copy>(
+    strategy: Strategy,
+    by_page: &[BTreeSet<T>],
+    shared: &BTreeSet<T>,
+    bytes: impl Fn(&BTreeSet<T>) -> u64,
+    pages_per_visit: usize,
+) -> Estimate {
+    let using = by_page.iter().filter(|units| !units.is_empty()).count();
+    let shared_bytes = bytes(shared);
+    let warm = by_page
+        .iter()
+        .map(|units| bytes(&units.difference(shared).copied().collect()))
+        .collect::<Vec<_>>();
+    let cold = by_page
+        .iter()
+        .zip(warm.iter())
+        .map(|(units, warm)| match units.is_empty() {
+            true => 0,
+            false => shared_bytes + warm,
+        })
+        .collect();
+    let visit = pages_per_visit.min(using) as u64;
+    let per_visit = shared_bytes + visit * warm.iter().sum::<u64>() / using.max(1) as u64;
+    Estimate {
+        strategy,
+        per_visit,
+        cold,
+        warm,
+    }
+}
+
+/// Pick the cheapest strategy for a family given the characters each page needs
+fn plan_family<'a>(
+    family: &'a Family,
+    by_page: Vec<BTreeSet<char>>,
+    clusters: BTreeSet<SmolStr>,
+    options: &PlanOptions,
+) -> FamilyPlan<'a> {
+    let union = by_page.iter().flatten().copied().collect::<BTreeSet<_>>();
+    let common = common_units(&by_page, options.shared_min_share);
+    let chars = |strategy| match strategy {
+        Strategy::SiteWide => union.clone(),
+        Strategy::Hybrid => common.clone(),
+        Strategy::PageSpecific | Strategy::Slices => BTreeSet::new(),
+    };
+    let char_bytes = |chars: &BTreeSet<char>| bytes(family, chars.len());
+    let mut estimates = [Strategy::SiteWide, Strategy::Hybrid, Strategy::PageSpecific]
+        .into_iter()
+        .map(|strategy| {
+            estimate(
+                strategy,
+                &by_page,
+                &chars(strategy),
+                char_bytes,
+                options.pages_per_visit,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut slices = Vec::new();
+    if let Some(family_slices) = family.slices.as_ref() {
+        slices = by_page
+            .iter()
+            .map(|chars| family.slices_for(chars.iter().copied()).unwrap_or_default())
+            .collect();
+        let slice_bytes = |slices: &BTreeSet<usize>| {
+            let bytes = slices
+                .iter()
+                .map(|idx| slice_bytes(family, &family_slices[*idx]))
+                .sum::<u64>();
+            (bytes as f64 * (1.0 - options.slice_hit_rate)).round() as u64
+        };
+        estimates.push(estimate(
+            Strategy::Slices,
+            &slices,
+            &common_units(&slices, options.shared_min_share),
+            slice_bytes,
+            options.pages_per_visit,
+        ));
+    }
+
+    let strategy = estimates
+        .iter()
+        .min_by_key(|estimate| (estimate.per_visit, estimate.strategy.rank()))
+        .map(|estimate| estimate.strategy)
+        .unwrap();
+    let shared = chars(strategy);
+    let pages = match strategy {
+        Strategy::Slices => vec![BTreeSet::new(); by_page.len()],
+        _ => by_page
+            .iter()
+            .map(|chars| chars.difference(&shared).copied().collect())
+            .collect(),
+    };
+    FamilyPlan {
+        family,
+        strategy,
+        shared,
+        pages,
+        slices,
+        used: by_page.iter().map(|chars| !chars.is_empty()).collect(),
+        clusters,
+        estimates,
+    }
+}
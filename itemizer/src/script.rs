@@ -0,0 +1,88 @@
+//! Script runs, the way a shaper wants text split, following
+//! [UAX #24](https://www.unicode.org/reports/tr24/) much as ICU's usc_nextRun does
+
+use icu_properties::{
+    Script,
+    bidi_data::{self, BidiPairingProperties},
+    script,
+};
+
+/// Matching a closing bracket looks no further back than this many open brackets
+const MAX_OPEN_BRACKETS: usize = 32;
+
+/// True if characters of script take their script from the text around them
+pub(crate) fn is_neutral(script: Script) -> bool {
+    script == Script::Common || script == Script::Inherited
+}
+
+/// The script of the first character of grapheme, Common if it's empty
+pub(crate) fn base_script(grapheme: &str) -> Script {
+    grapheme
+        .chars()
+        .next()
+        .map(|c| script::script_with_extensions().get_script_val(c as u32))
+        .unwrap_or(Script::Common)
+}
+
+/// True unless the Script_Extensions of the first character of grapheme name specific scripts
+/// and script isn't one of them, e.g. false for 。 and Latin
+pub(crate) fn extends_to(grapheme: &str, script: Script) -> bool {
+    let Some(c) = grapheme.chars().next() else {
+        return true;
+    };
+    let extensions = script::script_with_extensions().get_script_extensions_val(c as u32);
+    extensions.iter().all(is_neutral) || extensions.contains(&script)
+}
+
+/// Split text into (start, end, script) runs
+///
+/// Common and Inherited characters join the run before them, or the one after if they lead the
+/// text. A closing bracket takes the script of the run its opening bracket was in so, e.g., the
+/// parentheses in "abc (αβγ)" are both Latin. A character whose Script_Extensions include the
+/// script of the current run stays in it. Text with no script at all is a single Common run.
+pub(crate) fn script_runs(text: &str) -> Vec<(usize, usize, Script)> {
+    let scripts = script::script_with_extensions();
+    let brackets = bidi_data::bidi_auxiliary_properties();
+
+    let mut runs: Vec<(usize, usize, Script)> = Vec::new();
+    // (opening bracket, script of the run it opened in)
+    let mut open: Vec<(char, Script)> = Vec::new();
+    let mut start = 0;
+    let mut current = Script::Common;
+    for (offset, c) in text.char_indices() {
+        let mut script = scripts.get_script_val(c as u32);
+        match brackets.get32_pairing_props(c as u32) {
+            BidiPairingProperties::Open(_) => {
+                if open.len() == MAX_OPEN_BRACKETS {
+                    open.remove(0);
+                }
+                open.push((c, current));
+            }
+            BidiPairingProperties::Close(opener) => {
+                if let Some(idx) = open.iter().rposition(|(o, _)| *o == opener) {
+                    script = open[idx].1;
+                    open.truncate(idx);
+                }
+            }
+            _ => (),
+        }
+
+        if is_neutral(script) || script == current || scripts.has_script(c as u32, current) {
+            continue;
+        }
+        if is_neutral(current) {
+            // Leading neutrals, and brackets they opened, take the first real script
+            for (_, s) in open.iter_mut().filter(|(_, s)| is_neutral(*s)) {
+                *s = script;
+            }
+        } else {
+            runs.push((start, offset, current));
+            start = offset;
+        }
+        current = script;
+    }
+    if start < text.len() {
+        runs.push((start, text.len(), current));
+    }
+    runs
+}
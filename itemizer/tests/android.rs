@@ -0,0 +1,493 @@
+//! Itemization outcomes observed on Android, reproduced against a chain shaped like Android's fonts.xml
+
+mod common;
+
+use common::{chain, itemize_with};
+use itemizer::{
+    ItemizeOptions, MissingCoverage,
+    explain::{MatchPath, Reason},
+    fallback_chain::FallbackChain,
+    locale::LocaleList,
+};
+
+/// Assert text itemizes, with default options, to runs of (text, family name)
+fn assert_runs(text: &str, lang: &str, expected: &[(&str, &str)]) {
+    let expected = expected
+        .iter()
+        .map(|(t, f)| (*t, Some(*f)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        expected,
+        itemize_with(text, lang, &ItemizeOptions::default()),
+        "{text:?} lang {lang:?}"
+    );
+}
+
+#[test]
+fn han_without_lang_uses_first_cjk_family() {
+    assert_runs("漢字", "", &[("漢字", "Noto Sans SC")]);
+}
+
+#[test]
+fn han_follows_requested_lang() {
+    assert_runs("漢字", "ja", &[("漢字", "Noto Sans JP")]);
+    assert_runs("漢字", "ko", &[("漢字", "Noto Sans KR")]);
+    assert_runs("漢字", "zh-Hant", &[("漢字", "Noto Sans TC")]);
+    assert_runs("漢字", "zh-Hans", &[("漢字", "Noto Sans SC")]);
+}
+
+#[test]
+fn han_follows_maximized_lang() {
+    assert_runs("漢字", "ja-JP", &[("漢字", "Noto Sans JP")]);
+    assert_runs("漢字", "ko-KR", &[("漢字", "Noto Sans KR")]);
+    assert_runs("漢字", "zh-TW", &[("漢字", "Noto Sans TC")]);
+    assert_runs("漢字", "zh-HK", &[("漢字", "Noto Sans TC")]);
+    assert_runs("漢字", "zh-CN", &[("漢字", "Noto Sans SC")]);
+    assert_runs("漢字", "zh", &[("漢字", "Noto Sans SC")]);
+    assert_runs("漢字", "zh_TW", &[("漢字", "Noto Sans TC")]);
+}
+
+#[test]
+fn invalid_lang_is_an_error() {
+    let mut runs = Vec::new();
+    assert!(chain().itemize("漢字", "not a tag", &mut runs).is_err());
+}
+
+#[test]
+fn spaced_and_weighted_langs_parse() {
+    let expected = LocaleList::parse("ja,ko").unwrap();
+    for tags in ["ja, ko", " ja ,ko ", "ja,ko;q=0.9", "ja;q=1.0, ko;q=0.5"] {
+        assert_eq!(expected, LocaleList::parse(tags).unwrap(), "{tags:?}");
+    }
+    assert_eq!(LocaleList::default(), LocaleList::parse(" ").unwrap());
+    assert!(LocaleList::parse("ja, ,ko").is_err());
+    assert_runs("漢字", "ko, ja", &[("漢字", "Noto Sans KR")]);
+}
+
+#[test]
+fn first_requested_lang_dominates() {
+    assert_runs("漢字", "ko,ja", &[("漢字", "Noto Sans KR")]);
+    assert_runs("漢字", "en,ja,ko", &[("漢字", "Noto Sans JP")]);
+}
+
+#[test]
+fn parsed_locale_list() {
+    let text = "漢字 한국어";
+    let mut runs = Vec::new();
+    chain()
+        .itemize_locales(text, &"ja,ko,en".parse().unwrap(), &mut runs)
+        .unwrap();
+    let runs = runs
+        .iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.unwrap().family_name.as_str(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            ("漢字", "Noto Sans JP"),
+            (" ", "Roboto"),
+            ("한국어", "Noto Sans KR")
+        ],
+        runs
+    );
+}
+
+#[test]
+fn kana_and_hangul_have_only_one_supporter() {
+    assert_runs("ひらがな", "zh-Hans", &[("ひらがな", "Noto Sans JP")]);
+    assert_runs("한국어", "", &[("한국어", "Noto Sans KR")]);
+}
+
+#[test]
+fn compact_variant_preferred() {
+    assert_runs("مرحبا", "", &[("مرحبا", "Noto Naskh Arabic UI")]);
+    assert_runs("नमस्ते", "hi", &[("नमस्ते", "Noto Sans Devanagari UI")]);
+    assert_runs("বাংলা", "bn", &[("বাংলা", "Noto Sans Bengali UI")]);
+}
+
+#[test]
+fn emoji_presentation_selector() {
+    assert_runs(
+        "\u{2764}\u{fe0f}",
+        "",
+        &[("\u{2764}\u{fe0f}", "Noto Color Emoji")],
+    );
+}
+
+#[test]
+fn text_presentation_selector() {
+    assert_runs(
+        "\u{2764}\u{fe0e}",
+        "",
+        &[("\u{2764}\u{fe0e}", "Noto Sans Symbols 2")],
+    );
+}
+
+#[test]
+fn default_presentation() {
+    // U+2764 HEAVY BLACK HEART and U+2665 BLACK HEART SUIT are text by default
+    assert_runs("\u{2764}", "", &[("\u{2764}", "Noto Sans Symbols 2")]);
+    assert_runs("\u{2665}", "", &[("\u{2665}", "Noto Sans Symbols 2")]);
+    // U+2705 WHITE HEAVY CHECK MARK and U+26BD SOCCER BALL are emoji by default
+    assert_runs("\u{2705}", "", &[("\u{2705}", "Noto Color Emoji")]);
+    assert_runs("\u{26bd}", "", &[("\u{26bd}", "Noto Color Emoji")]);
+    // The head wins anything it supports without a variation selector
+    assert_runs("\u{a9}1", "", &[("\u{a9}1", "Roboto")]);
+}
+
+#[test]
+fn presentation_selector_beats_default() {
+    assert_runs(
+        "\u{2665}\u{fe0f}",
+        "",
+        &[("\u{2665}\u{fe0f}", "Noto Color Emoji")],
+    );
+    assert_runs(
+        "\u{2705}\u{fe0e}",
+        "",
+        &[("\u{2705}\u{fe0e}", "Noto Sans Symbols 2")],
+    );
+    assert_runs(
+        "\u{a9}\u{fe0f}",
+        "",
+        &[("\u{a9}\u{fe0f}", "Noto Color Emoji")],
+    );
+    assert_runs(
+        "1\u{fe0f}\u{20e3}",
+        "",
+        &[("1\u{fe0f}\u{20e3}", "Noto Color Emoji")],
+    );
+}
+
+#[test]
+fn emoji_style_from_lang() {
+    assert_runs("\u{2764}", "und-Zsye", &[("\u{2764}", "Noto Color Emoji")]);
+    assert_runs(
+        "\u{2705}",
+        "en-u-em-text",
+        &[("\u{2705}", "Noto Sans Symbols 2")],
+    );
+    assert_runs(
+        "\u{2764}",
+        "und-Zsym",
+        &[("\u{2764}", "Noto Sans Symbols 2")],
+    );
+    assert_runs(
+        "\u{2764}",
+        "en-u-em-text",
+        &[("\u{2764}", "Noto Sans Symbols 2")],
+    );
+}
+
+#[test]
+fn sticky_punctuation_stays_with_previous_family() {
+    assert_runs("世界!", "ja", &[("世界!", "Noto Sans JP")]);
+    assert_runs(
+        "世界, 日本",
+        "ja",
+        &[
+            ("世界,", "Noto Sans JP"),
+            (" ", "Roboto"),
+            ("日本", "Noto Sans JP"),
+        ],
+    );
+}
+
+#[test]
+fn mixed_scripts() {
+    assert_runs(
+        "Hello 世界 مرحبا 😀",
+        "ja",
+        &[
+            ("Hello ", "Roboto"),
+            ("世界", "Noto Sans JP"),
+            (" ", "Roboto"),
+            ("مرحبا", "Noto Naskh Arabic UI"),
+            (" ", "Roboto"),
+            ("😀", "Noto Color Emoji"),
+        ],
+    );
+}
+
+#[test]
+fn explain_reasons() {
+    let chain = chain();
+    let reasons = |text: &str, lang: &str| {
+        chain
+            .explain(text, lang)
+            .unwrap()
+            .into_iter()
+            .map(|d| d.reason)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(vec![Reason::HeadPreference], reasons("a", "ja"));
+    assert_eq!(vec![Reason::LangMatch], reasons("字", "ja"));
+    assert_eq!(vec![Reason::FirstInChain], reasons("字", ""));
+    assert_eq!(vec![Reason::VariantMatch], reasons("م", ""));
+    assert_eq!(vec![Reason::Presentation], reasons("\u{2764}\u{fe0e}", ""));
+    assert_eq!(vec![Reason::OnlySupporter], reasons("한", ""));
+    assert_eq!(
+        vec![Reason::LangMatch, Reason::Continuity],
+        reasons("字!", "ja")
+    );
+    assert_eq!(vec![Reason::Unsupported], reasons("\u{10000}", ""));
+}
+
+#[test]
+fn han_jumps_for_single_lang() {
+    let chain = chain();
+    let path = |lang: &str| chain.explain("字", lang).unwrap()[0].path;
+    for lang in ["", "ja", "ja-JP", "ko", "zh-TW", "zh-Hans"] {
+        assert_eq!(MatchPath::LangJump, path(lang), "{lang}");
+    }
+    // No family supports en, and tables are only built for single locales
+    assert_eq!(MatchPath::Walk, path("en"));
+    assert_eq!(MatchPath::Walk, path("ko,ja"));
+}
+
+#[test]
+fn lang_tables_survive_serialization() {
+    let chain = chain();
+    let loaded = FallbackChain::from_bytes(&chain.to_bytes()).unwrap();
+    let text = "漢字 ひらがな 한국어 مرحبا ❤️ \u{1f468}\u{200d}\u{1f680} \u{1f44d}\u{1f3fd}";
+    for lang in ["", "ja", "ko", "zh-Hant", "ko,ja"] {
+        let runs = |chain: &FallbackChain| {
+            let mut runs = Vec::new();
+            chain.itemize(text, lang, &mut runs).unwrap();
+            runs.iter()
+                .map(|r| (r.start, r.end, r.family.map(|f| f.family_name.clone())))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(runs(chain), runs(&loaded), "{lang}");
+        assert_eq!(
+            chain
+                .explain(text, lang)
+                .unwrap()
+                .iter()
+                .map(|d| d.path)
+                .collect::<Vec<_>>(),
+            loaded
+                .explain(text, lang)
+                .unwrap()
+                .iter()
+                .map(|d| d.path)
+                .collect::<Vec<_>>(),
+            "{lang}"
+        );
+    }
+}
+
+#[test]
+fn unsupported_text_is_reported() {
+    let text = "a\u{10000}\u{10001}b\u{e000}";
+    let mut runs = Vec::new();
+    let missing = chain().itemize(text, "", &mut runs).unwrap();
+    let runs = runs
+        .iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.map(|f| f.family_name.as_str()),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        vec![
+            ("a", Some("Roboto")),
+            ("\u{10000}\u{10001}", None),
+            ("b", Some("Roboto")),
+            ("\u{e000}", None),
+        ],
+        runs
+    );
+    assert_eq!(3, missing.graphemes);
+    assert_eq!(
+        vec![0xe000, 0x10000, 0x10001],
+        missing.codepoints.into_iter().collect::<Vec<_>>()
+    );
+}
+
+/// (run text, family name if any) for each run, and what was missing
+fn itemize_partial<'a>(
+    text: &'a str,
+    lang: &str,
+) -> (Vec<(&'a str, Option<&'static str>)>, MissingCoverage) {
+    let mut runs = Vec::new();
+    let missing = chain().itemize(text, lang, &mut runs).unwrap();
+    let runs = runs
+        .iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.map(|f| f.family_name.as_str()),
+            )
+        })
+        .collect();
+    (runs, missing)
+}
+
+#[test]
+fn split_grapheme_reports_unsupported_mark() {
+    let (runs, missing) = itemize_partial("a\u{0301}b", "");
+    assert_eq!(
+        vec![
+            ("a", Some("Roboto")),
+            ("\u{0301}", None),
+            ("b", Some("Roboto")),
+        ],
+        runs
+    );
+    assert_eq!(1, missing.graphemes);
+    assert_eq!(
+        vec![0x0301],
+        missing.codepoints.into_iter().collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn control_characters_stay_in_the_run() {
+    let (runs, missing) = itemize_partial("abc\ndef\tghi\r\n", "");
+    assert_eq!(vec![("abc\ndef\tghi\r\n", Some("Roboto"))], runs);
+    assert!(missing.is_empty());
+
+    let (runs, missing) = itemize_partial("世界\n한국어", "");
+    assert_eq!(
+        vec![
+            ("世界\n", Some("Noto Sans SC")),
+            ("한국어", Some("Noto Sans KR"))
+        ],
+        runs
+    );
+    assert!(missing.is_empty());
+}
+
+#[test]
+fn split_grapheme_across_families() {
+    let (runs, missing) = itemize_partial("a\u{20dd}", "");
+    assert_eq!(
+        vec![
+            ("a", Some("Roboto")),
+            ("\u{20dd}", Some("Noto Sans Symbols 2"))
+        ],
+        runs
+    );
+    assert!(missing.is_empty());
+
+    let decisions = chain().explain("a\u{20dd}", "").unwrap();
+    assert_eq!(
+        vec![
+            (0, 1, MatchPath::Partial, Reason::PartialCoverage),
+            (1, 4, MatchPath::Partial, Reason::PartialCoverage),
+        ],
+        decisions
+            .iter()
+            .map(|d| (d.start, d.end, d.path, d.reason))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn whole_grapheme_preferred_to_split() {
+    let (runs, _) = itemize_partial("\u{2764}\u{20dd}", "");
+    assert_eq!(
+        vec![("\u{2764}\u{20dd}", Some("Noto Sans Symbols 2"))],
+        runs
+    );
+}
+
+#[test]
+fn default_ignorables_need_no_glyph() {
+    // ZWNJ inside a Persian word, which the Arabic families lack
+    assert_runs(
+        "می\u{200c}خواهم",
+        "fa",
+        &[("می\u{200c}خواهم", "Noto Naskh Arabic UI")],
+    );
+    // A word joiner alone doesn't break the run
+    assert_runs(
+        "世界\u{2060}日本",
+        "ja",
+        &[("世界\u{2060}日本", "Noto Sans JP")],
+    );
+    assert_runs("\u{200d}a", "", &[("\u{200d}a", "Roboto")]);
+}
+
+#[test]
+fn zwj_matters_in_emoji_sequences() {
+    let (runs, missing) = itemize_partial("\u{1f469}\u{200d}\u{1f680}", "");
+    assert_eq!(
+        vec![("\u{1f469}\u{200d}\u{1f680}", Some("Noto Color Emoji"))],
+        runs
+    );
+    assert!(missing.is_empty());
+    assert_eq!(
+        MatchPath::Walk,
+        chain().explain("\u{1f469}\u{200d}\u{1f680}", "").unwrap()[0].path
+    );
+}
+
+#[test]
+fn emoji_sequences_need_a_ligature() {
+    // Both emoji families ligate it, the first wins
+    assert_runs(
+        "\u{1f469}\u{200d}\u{1f680}",
+        "",
+        &[("\u{1f469}\u{200d}\u{1f680}", "Noto Color Emoji")],
+    );
+    // Only the update does
+    assert_runs(
+        "\u{1f468}\u{200d}\u{1f680}",
+        "",
+        &[("\u{1f468}\u{200d}\u{1f680}", "Noto Color Emoji Update")],
+    );
+}
+
+#[test]
+fn unligated_sequence_falls_back_to_components() {
+    // U+1F44D THUMBS UP SIGN with a skin tone modifier, which no family ligates
+    let text = "\u{1f44d}\u{1f3fd}";
+    let (runs, missing) = itemize_partial(text, "");
+    assert_eq!(vec![(text, Some("Noto Color Emoji"))], runs);
+    assert!(missing.is_empty());
+    assert_eq!(
+        vec![(0, 4, MatchPath::Partial), (4, 8, MatchPath::Partial)],
+        chain()
+            .explain(text, "")
+            .unwrap()
+            .iter()
+            .map(|d| (d.start, d.end, d.path))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn regional_indicator_pairs_are_flags() {
+    // JP then US
+    let text = "\u{1f1ef}\u{1f1f5}\u{1f1fa}\u{1f1f8}";
+    let (runs, missing) = itemize_partial(text, "");
+    assert_eq!(vec![(text, Some("Noto Color Emoji"))], runs);
+    assert!(missing.is_empty());
+}
+
+#[test]
+fn unpaired_regional_indicator_is_a_letter() {
+    assert_runs("\u{1f1ef}", "", &[("\u{1f1ef}", "Noto Sans Symbols 2")]);
+}
+
+#[test]
+fn flag_no_family_draws_is_reported() {
+    // ZZ is not a region, its letters are drawn as text
+    let text = "\u{1f1ff}\u{1f1ff}";
+    let (runs, missing) = itemize_partial(text, "");
+    assert_eq!(vec![(text, Some("Noto Sans Symbols 2"))], runs);
+    assert_eq!(0, missing.graphemes);
+    assert_eq!(
+        vec!["ZZ"],
+        missing.flags.iter().map(|f| f.as_str()).collect::<Vec<_>>()
+    );
+    assert!(!missing.is_empty());
+}
@@ -0,0 +1,54 @@
+//! Splitting runs by bidi level and drawing mirrored characters, see
+//! [itemizer::ItemizeOptions::bidi]
+
+mod common;
+
+use common::runs_with;
+use itemizer::{Direction, ItemizeOptions};
+
+/// (run text, family name, level) for each run when splitting by bidi level
+fn itemize_bidi(text: &str) -> Vec<(&str, &'static str, u8)> {
+    let options = ItemizeOptions {
+        bidi: true,
+        ..Default::default()
+    };
+    let runs = runs_with(text, "", &options);
+    for run in runs.iter() {
+        assert_eq!(run.level.map(Direction::of_level), run.direction);
+    }
+    runs.iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.unwrap().family_name.as_str(),
+                r.level.unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn bidi_runs_split_at_level_changes() {
+    // Latin embedded in Arabic, the space between them taking the paragraph's level
+    assert_eq!(
+        vec![
+            ("مرحبا", "Noto Naskh Arabic UI", 1),
+            (" ", "Roboto", 1),
+            ("abc 123", "Roboto", 2),
+        ],
+        itemize_bidi("مرحبا abc 123")
+    );
+}
+
+#[test]
+fn bidi_mirrored_needs_its_mirror() {
+    // U+2045 LEFT SQUARE BRACKET WITH QUILL, Roboto lacks its mirror, U+2046
+    assert_eq!(vec![("a\u{2045}", "Roboto", 0)], itemize_bidi("a\u{2045}"));
+    assert_eq!(
+        vec![
+            ("\u{0627}", "Noto Naskh Arabic UI", 1),
+            ("\u{2045}", "Noto Sans Symbols 2", 1),
+        ],
+        itemize_bidi("\u{0627}\u{2045}")
+    );
+}
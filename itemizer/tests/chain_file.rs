@@ -0,0 +1,131 @@
+//! Loading compiled chains, see [itemizer::chain_file]
+
+use itemizer::{Error, chain_file::VERSION, fallback_chain::FallbackChain};
+
+/// Little-endian writes in the layout of [itemizer::chain_file]
+#[derive(Default)]
+struct Bytes(Vec<u8>);
+
+impl Bytes {
+    fn u8(mut self, v: u8) -> Self {
+        self.0.push(v);
+        self
+    }
+
+    fn u16(mut self, v: u16) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn str(mut self, v: &str) -> Self {
+        self = self.u32(v.len() as u32);
+        self.0.extend_from_slice(v.as_bytes());
+        self
+    }
+}
+
+/// A chain of one family, Roboto, covering start..=end, mapped through a table mapping it to
+/// family
+fn chain_bytes(start: u32, end: u32, family: u32) -> Vec<u8> {
+    let mut bytes = Bytes(b"ITMZ".to_vec()).u16(VERSION).str("sans-serif");
+    for _ in 0..6 {
+        bytes = bytes.u32(0);
+    }
+    bytes
+        .u32(1)
+        .str("Roboto")
+        .str("")
+        .u8(0)
+        .u8(0)
+        .u32(1)
+        .u32(start)
+        .u32(end)
+        .u8(0)
+        .u8(0)
+        .u8(0)
+        .u32(1)
+        .u32(start)
+        .u32(end)
+        .u32(family)
+        .u32(0)
+        .0
+}
+
+#[test]
+fn hand_built_chain_loads() {
+    let chain = FallbackChain::from_bytes(&chain_bytes(0x20, 0x7e, 0)).unwrap();
+    assert_eq!("sans-serif", chain.name());
+    assert_eq!(95, chain.families()[0].codepoints.len());
+}
+
+#[test]
+fn ranges_beyond_unicode_are_malformed() {
+    for end in [0x110000, 0x7fffff, u32::MAX] {
+        assert!(
+            matches!(
+                FallbackChain::from_bytes(&chain_bytes(0, end, 0)),
+                Err(Error::MalformedChainData(_))
+            ),
+            "{end:#x}"
+        );
+    }
+    assert!(FallbackChain::from_bytes(&chain_bytes(0x10fff0, 0x10ffff, 0)).is_ok());
+}
+
+fn malformed(data: &[u8]) -> bool {
+    matches!(
+        FallbackChain::from_bytes(data),
+        Err(Error::MalformedChainData(_))
+    )
+}
+
+#[test]
+fn bad_magic_is_malformed() {
+    let mut data = chain_bytes(0x20, 0x7e, 0);
+    data[0] = b'X';
+    assert!(malformed(&data));
+    assert!(malformed(b""));
+}
+
+#[test]
+fn truncated_data_is_malformed() {
+    let data = chain_bytes(0x20, 0x7e, 0);
+    for len in [3, 5, 20, data.len() - 1] {
+        assert!(malformed(&data[..len]), "{len}");
+    }
+}
+
+#[test]
+fn trailing_bytes_are_malformed() {
+    let mut data = chain_bytes(0x20, 0x7e, 0);
+    data.push(0);
+    assert!(malformed(&data));
+}
+
+#[test]
+fn mapping_to_missing_family_is_malformed() {
+    assert!(malformed(&chain_bytes(0x20, 0x7e, 1)));
+}
+
+#[test]
+fn other_versions_are_unsupported() {
+    let mut data = chain_bytes(0x20, 0x7e, 0);
+    data[4..6].copy_from_slice(&(VERSION - 1).to_le_bytes());
+    assert!(matches!(
+        FallbackChain::from_bytes(&data),
+        Err(Error::UnsupportedChainVersion(v)) if v == VERSION - 1
+    ));
+}
+
+#[test]
+fn missing_file_is_an_io_error() {
+    assert!(matches!(
+        FallbackChain::load("/nonexistent/sans-serif.chain"),
+        Err(Error::Io(_))
+    ));
+}
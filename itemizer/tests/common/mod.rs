@@ -0,0 +1,261 @@
+//! A chain shaped like Android's fonts.xml, shared by the tests that itemize against it
+
+// Each test file uses only some of the helpers
+#![allow(dead_code)]
+
+use std::{collections::HashSet, sync::OnceLock};
+
+use itemizer::{
+    ItemizeOptions, Run,
+    fallback_chain::{Cost, FallbackChain, Family, Variant},
+    locale::LocaleList,
+};
+
+struct TestFamily {
+    name: &'static str,
+    lang: Option<&'static str>,
+    variant: Variant,
+    emoji: bool,
+    sequences: Option<&'static [&'static [u32]]>,
+    cost: Option<Cost>,
+    ranges: &'static [(u32, u32)],
+}
+
+const ASCII: (u32, u32) = (0x20, 0x7e);
+const CJK_PUNCTUATION: (u32, u32) = (0x3000, 0x303f);
+const CJK_IDEOGRAPHS: (u32, u32) = (0x4e00, 0x9fff);
+
+const ARABIC: &[(u32, u32)] = &[(0x0600, 0x06ff)];
+const DEVANAGARI: &[(u32, u32)] = &[(0x0900, 0x097f)];
+const BENGALI: &[(u32, u32)] = &[(0x0964, 0x0965), (0x0980, 0x09ff)];
+
+const CHAIN: &[TestFamily] = &[
+    TestFamily {
+        name: "Roboto",
+        lang: None,
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(60)),
+        ranges: &[
+            ASCII,
+            (0xa0, 0xff),
+            (0x0370, 0x04ff),
+            (0x2010, 0x2027),
+            (0x2045, 0x2045),
+        ],
+    },
+    TestFamily {
+        name: "Noto Naskh Arabic",
+        lang: Some("und-Arab"),
+        variant: Variant::Elegant,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(100)),
+        ranges: ARABIC,
+    },
+    TestFamily {
+        name: "Noto Naskh Arabic UI",
+        lang: Some("und-Arab"),
+        variant: Variant::Compact,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(100)),
+        ranges: ARABIC,
+    },
+    TestFamily {
+        name: "Noto Serif Devanagari",
+        lang: Some("und-Deva"),
+        variant: Variant::Elegant,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(150)),
+        ranges: DEVANAGARI,
+    },
+    TestFamily {
+        name: "Noto Sans Devanagari UI",
+        lang: Some("und-Deva"),
+        variant: Variant::Compact,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(150)),
+        ranges: DEVANAGARI,
+    },
+    TestFamily {
+        name: "Noto Serif Bengali",
+        lang: Some("und-Beng"),
+        variant: Variant::Elegant,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(150)),
+        ranges: BENGALI,
+    },
+    TestFamily {
+        name: "Noto Sans Bengali UI",
+        lang: Some("und-Beng"),
+        variant: Variant::Compact,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(150)),
+        ranges: BENGALI,
+    },
+    TestFamily {
+        name: "Noto Sans SC",
+        lang: Some("zh-Hans"),
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(500)),
+        ranges: &[ASCII, CJK_PUNCTUATION, CJK_IDEOGRAPHS],
+    },
+    TestFamily {
+        name: "Noto Sans TC",
+        lang: Some("zh-Hant,zh-Bopo"),
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(600)),
+        ranges: &[ASCII, CJK_PUNCTUATION, (0x3100, 0x312f), CJK_IDEOGRAPHS],
+    },
+    TestFamily {
+        name: "Noto Sans JP",
+        lang: Some("ja"),
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::File(5_000_000)),
+        ranges: &[ASCII, CJK_PUNCTUATION, (0x3040, 0x30ff), CJK_IDEOGRAPHS],
+    },
+    TestFamily {
+        name: "Noto Sans KR",
+        lang: Some("ko"),
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(600)),
+        ranges: &[
+            ASCII,
+            (0x1100, 0x11ff),
+            CJK_PUNCTUATION,
+            CJK_IDEOGRAPHS,
+            (0xac00, 0xd7a3),
+        ],
+    },
+    TestFamily {
+        name: "Noto Color Emoji",
+        lang: Some("und-Zsye"),
+        variant: Variant::Default,
+        emoji: true,
+        sequences: Some(&[
+            &[0x31, 0x20e3],
+            &[0x1f1ef, 0x1f1f5],
+            &[0x1f1fa, 0x1f1f8],
+            &[0x1f469, 0x200d, 0x1f680],
+        ]),
+        cost: Some(Cost::PerCodepoint(2_000)),
+        ranges: &[
+            (0x23, 0x23),
+            (0x2a, 0x2a),
+            (0x30, 0x39),
+            (0xa9, 0xa9),
+            (0x200d, 0x200d),
+            (0x20e3, 0x20e3),
+            (0x2600, 0x27bf),
+            (0xfe0f, 0xfe0f),
+            (0x1f1e6, 0x1f1ff),
+            (0x1f300, 0x1faff),
+        ],
+    },
+    TestFamily {
+        name: "Noto Sans Symbols 2",
+        lang: Some("und-Zsym"),
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(30)),
+        ranges: &[
+            (0x20dd, 0x20dd),
+            (0x2045, 0x2046),
+            (0x2190, 0x21ff),
+            (0x2600, 0x27bf),
+            (0x1f1e6, 0x1f1ff),
+        ],
+    },
+    TestFamily {
+        name: "Noto Color Emoji Update",
+        lang: Some("und-Zsye"),
+        variant: Variant::Default,
+        emoji: true,
+        sequences: Some(&[&[0x1f468, 0x200d, 0x1f680], &[0x1f469, 0x200d, 0x1f680]]),
+        cost: Some(Cost::PerCodepoint(2_000)),
+        ranges: &[(0x200d, 0x200d), (0x1f468, 0x1f469), (0x1f680, 0x1f680)],
+    },
+    TestFamily {
+        name: "Noto Sans",
+        lang: None,
+        variant: Variant::Default,
+        emoji: false,
+        sequences: None,
+        cost: Some(Cost::PerCodepoint(40)),
+        ranges: &[ASCII, (0xa0, 0xff), (0x1e00, 0x1eff)],
+    },
+];
+
+/// The test chain, built once
+pub fn chain() -> &'static FallbackChain {
+    static SANS_SERIF: OnceLock<FallbackChain> = OnceLock::new();
+    SANS_SERIF.get_or_init(build_chain)
+}
+
+fn build_chain() -> FallbackChain {
+    let families = CHAIN
+        .iter()
+        .map(|f| Family {
+            lang: f.lang.map(Into::into),
+            variant: f.variant,
+            emoji: f.emoji,
+            sequences: f
+                .sequences
+                .map(|sequences| sequences.iter().map(|s| s.to_vec()).collect()),
+            cost: f.cost,
+            ..Family::new(f.name)
+        })
+        .collect();
+    FallbackChain::for_fonts("sans-serif", families, |family| {
+        CHAIN
+            .iter()
+            .find(|f| f.name == family.family_name)
+            .unwrap()
+            .ranges
+            .iter()
+            .flat_map(|(start, end)| *start..=*end)
+            .collect::<HashSet<_>>()
+    })
+    .unwrap()
+}
+
+/// The runs of text itemized against the test chain with options
+pub fn runs_with(text: &str, lang: &str, options: &ItemizeOptions) -> Vec<Run<'static>> {
+    let mut runs = Vec::new();
+    chain()
+        .itemize_with_options(text, &LocaleList::parse(lang).unwrap(), options, &mut runs)
+        .unwrap();
+    runs
+}
+
+/// (run text, family name if any) for each run of text itemized with options
+pub fn itemize_with<'a>(
+    text: &'a str,
+    lang: &str,
+    options: &ItemizeOptions,
+) -> Vec<(&'a str, Option<&'static str>)> {
+    runs_with(text, lang, options)
+        .iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.map(|f| f.family_name.as_str()),
+            )
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+//! Google Fonts css2 requests built from runs
+
+use itemizer::{
+    Run,
+    css2::{self, Css2Options, FontDisplay},
+    fallback_chain::Family,
+};
+
+fn run(family: Option<&Family>, start: usize, end: usize) -> Run<'_> {
+    Run {
+        family,
+        start,
+        end,
+        script: None,
+        level: None,
+        direction: None,
+    }
+}
+
+#[test]
+fn one_request_per_family() {
+    let roboto = Family::new("Roboto");
+    let jp = Family::new("Noto Sans JP");
+    let text = "b a 世界\na";
+    let runs = vec![
+        run(Some(&roboto), 0, 4),
+        run(Some(&jp), 4, 10),
+        run(None, 10, 11),
+        run(Some(&roboto), 11, 12),
+    ];
+    assert_eq!(
+        vec![
+            "https://fonts.googleapis.com/css2?family=Roboto&text=%20ab",
+            "https://fonts.googleapis.com/css2?family=Noto+Sans+JP&text=%E4%B8%96%E7%95%8C",
+        ],
+        css2::urls(text, &runs, &Css2Options::default())
+    );
+}
+
+#[test]
+fn long_requests_are_split() {
+    let roboto = Family::new("Roboto");
+    let text = "abcd+";
+    let runs = vec![run(Some(&roboto), 0, text.len())];
+    let prefix = "https://fonts.googleapis.com/css2?family=Roboto&display=swap&text=";
+    let options = Css2Options {
+        display: Some("swap".parse().unwrap()),
+        max_url_len: prefix.len() + 4,
+    };
+    assert_eq!(
+        vec![format!("{prefix}%2Ba"), format!("{prefix}bcd")],
+        css2::urls(text, &runs, &options)
+    );
+    assert!("sometimes".parse::<FontDisplay>().is_err());
+}
+
+#[test]
+fn sequences_stay_in_one_request() {
+    let emoji = Family::new("Noto Color Emoji");
+    let text = "\u{1f469}\u{200d}\u{1f680} \u{1f468}\u{200d}\u{1f680}";
+    let runs = vec![run(Some(&emoji), 0, text.len())];
+    let prefix = "https://fonts.googleapis.com/css2?family=Noto+Color+Emoji&text=";
+    let options = Css2Options {
+        display: None,
+        max_url_len: prefix.len() + 12,
+    };
+    assert_eq!(
+        vec![
+            format!("{prefix}%20"),
+            format!("{prefix}%E2%80%8D%F0%9F%91%A8%F0%9F%9A%80"),
+            format!("{prefix}%E2%80%8D%F0%9F%91%A9%F0%9F%9A%80"),
+        ],
+        css2::urls(text, &runs, &options)
+    );
+    // ZWJ and the rocket are in the URL already, only the woman is added
+    let options = Css2Options {
+        display: None,
+        max_url_len: prefix.len() + 48,
+    };
+    assert_eq!(
+        vec![format!(
+            "{prefix}%20%E2%80%8D%F0%9F%91%A8%F0%9F%9A%80%F0%9F%91%A9"
+        )],
+        css2::urls(text, &runs, &options)
+    );
+    // Not quite room for the woman, the whole sequence moves to the next URL
+    let options = Css2Options {
+        display: None,
+        max_url_len: prefix.len() + 47,
+    };
+    assert_eq!(
+        vec![
+            format!("{prefix}%20%E2%80%8D%F0%9F%91%A8%F0%9F%9A%80"),
+            format!("{prefix}%E2%80%8D%F0%9F%91%A9%F0%9F%9A%80"),
+        ],
+        css2::urls(text, &runs, &options)
+    );
+}
@@ -0,0 +1,43 @@
+//! Building chains and the limits of what they'll itemize
+
+use std::collections::HashSet;
+
+use itemizer::{
+    Error,
+    fallback_chain::{FallbackChain, Family, MAX_TEXT_LEN},
+};
+
+fn ascii(family: &Family) -> HashSet<u32> {
+    match family.family_name.as_str() {
+        "Roboto" => (0x20..=0x7e).collect(),
+        _ => HashSet::new(),
+    }
+}
+
+#[test]
+fn chain_without_families_is_an_error() {
+    assert!(matches!(
+        FallbackChain::for_fonts("sans-serif", Vec::new(), ascii),
+        Err(Error::EmptyChain)
+    ));
+}
+
+#[test]
+fn family_without_coverage_is_an_error() {
+    let families = vec![Family::new("Roboto"), Family::new("Missing Sans")];
+    assert!(matches!(
+        FallbackChain::for_fonts("sans-serif", families, ascii),
+        Err(Error::NoCoverage(name)) if name == "Missing Sans"
+    ));
+}
+
+#[test]
+fn text_beyond_the_limit_is_an_error() {
+    let chain = FallbackChain::for_fonts("sans-serif", vec![Family::new("Roboto")], ascii).unwrap();
+    let mut runs = Vec::new();
+    let text = "a".repeat(MAX_TEXT_LEN + 1);
+    assert!(matches!(
+        chain.itemize(&text, "", &mut runs),
+        Err(Error::TextTooLarge { len, max }) if len == MAX_TEXT_LEN + 1 && max == MAX_TEXT_LEN
+    ));
+}
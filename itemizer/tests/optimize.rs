@@ -0,0 +1,244 @@
+//! Itemizing for the fewest families, the smallest download or a single headline family,
+//! against the chain the Android tests use
+
+mod common;
+
+use common::{chain, itemize_with};
+use itemizer::{
+    ItemizeOptions,
+    explain::{MatchPath, Reason},
+    locale::LocaleList,
+};
+
+#[test]
+fn minimal_families_cover_latin_with_cjk() {
+    assert_eq!(
+        vec![("abc ", Some("Roboto")), ("世界", Some("Noto Sans JP"))],
+        itemize_with("abc 世界", "ja", &ItemizeOptions::default())
+    );
+    assert_eq!(
+        vec![("abc 世界", Some("Noto Sans JP"))],
+        itemize_with(
+            "abc 世界",
+            "ja",
+            &ItemizeOptions {
+                minimize_families: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn minimal_families_respect_lang_and_presentation() {
+    // Han must stay Japanese, the emoji must stay color
+    assert_eq!(
+        vec![
+            ("世界 ", Some("Noto Sans JP")),
+            ("\u{1f600}", Some("Noto Color Emoji")),
+        ],
+        itemize_with(
+            "世界 \u{1f600}",
+            "ja",
+            &ItemizeOptions {
+                minimize_families: true,
+                ..Default::default()
+            }
+        )
+    );
+    // Latin can go to either CJK family but Han can't
+    assert_eq!(
+        vec![
+            ("漢字 abc ", Some("Noto Sans JP")),
+            ("한국어", Some("Noto Sans KR")),
+        ],
+        itemize_with(
+            "漢字 abc 한국어",
+            "ja",
+            &ItemizeOptions {
+                minimize_families: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn cheapest_family_per_codepoint_wins() {
+    // Noto Sans costs less per codepoint than Roboto, the head
+    assert_eq!(
+        vec![("abc", Some("Noto Sans"))],
+        itemize_with(
+            "abc",
+            "",
+            &ItemizeOptions {
+                minimize_download: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn whole_file_paid_for_is_reused() {
+    // Japanese needs all of Noto Sans JP, after which its Latin is free
+    assert_eq!(
+        vec![("世界 abc", Some("Noto Sans JP"))],
+        itemize_with(
+            "世界 abc",
+            "ja",
+            &ItemizeOptions {
+                minimize_download: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn cheapest_family_honors_lang() {
+    // Noto Sans SC is cheaper but Traditional Chinese needs Noto Sans TC
+    assert_eq!(
+        vec![("漢字", Some("Noto Sans TC"))],
+        itemize_with(
+            "漢字",
+            "zh-Hant",
+            &ItemizeOptions {
+                minimize_download: true,
+                ..Default::default()
+            }
+        )
+    );
+    assert_eq!(
+        vec![("漢字", Some("Noto Sans SC"))],
+        itemize_with(
+            "漢字",
+            "",
+            &ItemizeOptions {
+                minimize_download: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn headline_in_one_family() {
+    let mut runs = Vec::new();
+    let headline = chain()
+        .itemize_headline(
+            "Hello 世界",
+            &LocaleList::parse("ja").unwrap(),
+            &ItemizeOptions::default(),
+            &mut runs,
+        )
+        .unwrap();
+    assert_eq!(
+        Some("Noto Sans JP"),
+        headline.family.map(|f| f.family_name.as_str())
+    );
+    assert_eq!(1, runs.len());
+    assert_eq!((8, 8), (headline.covered, headline.total));
+    assert!(!headline.is_mixed());
+}
+
+#[test]
+fn headline_falls_back_for_the_rest() {
+    let text = "Hello 世界 \u{1f600}";
+    let mut runs = Vec::new();
+    let headline = chain()
+        .itemize_headline(
+            text,
+            &LocaleList::parse("ja").unwrap(),
+            &ItemizeOptions::default(),
+            &mut runs,
+        )
+        .unwrap();
+    assert_eq!(
+        vec![
+            ("Hello 世界 ", Some("Noto Sans JP")),
+            ("\u{1f600}", Some("Noto Color Emoji")),
+        ],
+        runs.iter()
+            .map(|r| (
+                &text[r.start..r.end],
+                r.family.map(|f| f.family_name.as_str())
+            ))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!((9, 10), (headline.covered, headline.total));
+    assert!(headline.is_mixed());
+    assert_eq!(0.9, headline.share());
+}
+
+#[test]
+fn headline_counts_split_graphemes_once() {
+    let text = "a\u{300}";
+    let mut runs = Vec::new();
+    let headline = chain()
+        .itemize_headline(
+            text,
+            &LocaleList::default(),
+            &ItemizeOptions::default(),
+            &mut runs,
+        )
+        .unwrap();
+    assert_eq!(2, runs.len(), "{runs:?}");
+    assert_eq!(
+        Some("Roboto"),
+        headline.family.map(|f| f.family_name.as_str())
+    );
+    assert_eq!((0, 1), (headline.covered, headline.total));
+}
+
+#[test]
+fn explain_with_options_shows_moved_graphemes() {
+    let chain = chain();
+    let explain_text = |text: &str, options: &ItemizeOptions| {
+        chain
+            .explain_with_options(text, &LocaleList::parse("ja").unwrap(), options)
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.path, d.reason, d.family.map(|f| f.family_name.as_str())))
+            .collect::<Vec<_>>()
+    };
+    let explain = |options: &ItemizeOptions| explain_text("abc 世界", options);
+    let default = explain(&ItemizeOptions::default());
+    assert_eq!(
+        (MatchPath::Jump, Reason::HeadPreference, Some("Roboto")),
+        default[0]
+    );
+    assert_eq!(
+        default,
+        chain
+            .explain_locales("abc 世界", &LocaleList::parse("ja").unwrap())
+            .unwrap()
+            .into_iter()
+            .map(|d| (d.path, d.reason, d.family.map(|f| f.family_name.as_str())))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(
+        (MatchPath::Cover, Reason::Cover, Some("Noto Sans JP")),
+        explain(&ItemizeOptions {
+            minimize_families: true,
+            ..Default::default()
+        })[0]
+    );
+    assert_eq!(
+        (MatchPath::Headline, Reason::Headline, Some("Noto Sans JP")),
+        explain(&ItemizeOptions {
+            headline: true,
+            ..Default::default()
+        })[0]
+    );
+    assert_eq!(
+        (MatchPath::Word, Reason::WholeWord, Some("Noto Sans")),
+        explain_text(
+            "Vi\u{1ec7}t Nam",
+            &ItemizeOptions {
+                stable_words: true,
+                ..Default::default()
+            }
+        )[0]
+    );
+}
@@ -0,0 +1,200 @@
+//! Site wide plans of subset requests
+
+use std::collections::{BTreeSet, HashSet};
+
+use itemizer::{
+    Run,
+    fallback_chain::{Cost, FallbackChain, Family, Slice},
+    locale::LocaleList,
+    planner::{Page, PlanOptions, Strategy},
+};
+
+/// Roboto for ASCII at 50 bytes a codepoint, JP for ideographs at 500
+fn chain() -> FallbackChain {
+    chain_with_slices(None)
+}
+
+fn chain_with_slices(jp_slices: Option<Vec<Slice>>) -> FallbackChain {
+    let roboto = Family {
+        cost: Some(Cost::PerCodepoint(50)),
+        ..Family::new("Roboto")
+    };
+    let jp = Family {
+        lang: Some("ja".into()),
+        cost: Some(Cost::PerCodepoint(500)),
+        slices: jp_slices,
+        ..Family::new("Noto Sans JP")
+    };
+    let families = vec![roboto, jp];
+    FallbackChain::for_fonts("sans-serif", families, |family| {
+        match family.family_name.as_str() {
+            "Roboto" => (0x20..=0x7e).collect::<HashSet<_>>(),
+            _ => (0x4e00..=0x9fff).collect(),
+        }
+    })
+    .unwrap()
+}
+
+fn pages(texts: &[&str]) -> Vec<Page> {
+    texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| Page {
+            name: format!("/{i}"),
+            text: text.to_string(),
+            lang: LocaleList::default(),
+        })
+        .collect()
+}
+
+#[test]
+fn overlapping_pages_share_a_subset() {
+    let chain = chain();
+    let plan = chain
+        .plan(&pages(&["abc", "abd", "abe"]), &PlanOptions::default())
+        .unwrap();
+    assert_eq!(1, plan.families.len());
+    let roboto = &plan.families[0];
+    // Hybrid shares ab and costs the same, the simpler plan wins
+    assert_eq!(
+        vec![
+            (Strategy::SiteWide, 250),
+            (Strategy::Hybrid, 250),
+            (Strategy::PageSpecific, 450)
+        ],
+        roboto
+            .estimates
+            .iter()
+            .map(|e| (e.strategy, e.per_visit))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(Strategy::SiteWide, roboto.strategy);
+    assert_eq!(
+        "abcde".chars().collect::<Vec<_>>(),
+        roboto.shared.iter().copied().collect::<Vec<_>>()
+    );
+    assert!(roboto.pages.iter().all(|chars| chars.is_empty()));
+    assert_eq!(
+        vec!["https://fonts.googleapis.com/css2?family=Roboto&text=abcde"],
+        plan.page_urls(1, &Default::default())
+    );
+}
+
+#[test]
+fn distinct_pages_request_their_own_subsets() {
+    let chain = chain();
+    let plan = chain
+        .plan(
+            &pages(&["世界", "日本", "東京", "大阪", "京都"]),
+            &PlanOptions::default(),
+        )
+        .unwrap();
+    let jp = &plan.families[0];
+    assert_eq!("Noto Sans JP", jp.family.family_name);
+    assert_eq!(Strategy::PageSpecific, jp.strategy);
+    assert_eq!(4500, jp.estimate(Strategy::SiteWide).unwrap().per_visit);
+    assert_eq!(3000, jp.estimate(Strategy::PageSpecific).unwrap().per_visit);
+    assert_eq!(
+        vec![1000; 5],
+        jp.estimate(Strategy::PageSpecific).unwrap().cold
+    );
+    assert!(jp.shared.is_empty());
+    assert_eq!(
+        vec!["https://fonts.googleapis.com/css2?family=Noto+Sans+JP&text=%E4%BA%AC%E9%83%BD"],
+        plan.page_urls(4, &Default::default())
+    );
+}
+
+#[test]
+fn common_characters_are_shared() {
+    let chain = chain();
+    let plan = chain
+        .plan(
+            &pages(&["the ab", "the cd", "the ij", "the kl", "世界"]),
+            &PlanOptions::default(),
+        )
+        .unwrap();
+    let roboto = &plan.families[0];
+    assert_eq!(Strategy::Hybrid, roboto.strategy);
+    assert_eq!(
+        " eht".chars().collect::<Vec<_>>(),
+        roboto.shared.iter().copied().collect::<Vec<_>>()
+    );
+    let hybrid = roboto.estimate(Strategy::Hybrid).unwrap();
+    assert_eq!(500, hybrid.per_visit);
+    assert_eq!(600, roboto.estimate(Strategy::SiteWide).unwrap().per_visit);
+    assert_eq!(
+        900,
+        roboto.estimate(Strategy::PageSpecific).unwrap().per_visit
+    );
+    // The page in Japanese doesn't use Roboto at all
+    assert_eq!(vec![300, 300, 300, 300, 0], hybrid.cold);
+    assert_eq!(vec![100, 100, 100, 100, 0], hybrid.warm);
+    assert_eq!(
+        vec![
+            "https://fonts.googleapis.com/css2?family=Roboto&text=%20eht",
+            "https://fonts.googleapis.com/css2?family=Roboto&text=cd",
+        ],
+        plan.page_urls(1, &Default::default())
+    );
+    assert_eq!(
+        vec!["https://fonts.googleapis.com/css2?family=Noto+Sans+JP&text=%E4%B8%96%E7%95%8C"],
+        plan.page_urls(4, &Default::default())
+    );
+}
+
+#[test]
+fn cached_slices_beat_requests_for_our_text() {
+    let mut place_names = "世界日本東京大阪都"
+        .chars()
+        .map(|c| (c as u32, c as u32))
+        .collect::<Vec<_>>();
+    place_names.sort();
+    let chain = chain_with_slices(Some(vec![
+        Slice {
+            name: "[0]".into(),
+            ranges: place_names,
+        },
+        Slice {
+            name: "[1]".into(),
+            ranges: vec![(0x9f00, 0x9fff)],
+        },
+    ]));
+
+    let text = "hi 世界";
+    let mut runs: Vec<Run> = Vec::new();
+    chain.itemize(text, "", &mut runs).unwrap();
+    assert_eq!(
+        vec![None, Some([0].into())],
+        runs.iter().map(|r| r.slices(text)).collect::<Vec<_>>()
+    );
+
+    let loaded = FallbackChain::from_bytes(&chain.to_bytes()).unwrap();
+    assert_eq!(chain.families(), loaded.families());
+
+    let pages = pages(&["世界", "日本", "東京", "大阪", "京都"]);
+    let plan = chain.plan(&pages, &PlanOptions::default()).unwrap();
+    // Slices have every codepoint in range, cold they never win
+    assert_eq!(Strategy::PageSpecific, plan.families[0].strategy);
+    assert_eq!(
+        4500,
+        plan.families[0]
+            .estimate(Strategy::Slices)
+            .unwrap()
+            .per_visit
+    );
+
+    let options = PlanOptions {
+        slice_hit_rate: 0.9,
+        ..Default::default()
+    };
+    let plan = chain.plan(&pages, &options).unwrap();
+    let jp = &plan.families[0];
+    assert_eq!(Strategy::Slices, jp.strategy);
+    assert_eq!(450, jp.estimate(Strategy::Slices).unwrap().per_visit);
+    assert_eq!(vec![BTreeSet::from([0]); 5], jp.slices);
+    assert_eq!(
+        vec!["https://fonts.googleapis.com/css2?family=Noto+Sans+JP"],
+        plan.page_urls(4, &Default::default())
+    );
+}
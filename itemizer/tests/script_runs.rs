@@ -0,0 +1,68 @@
+//! Splitting runs by script as well as family, see [itemizer::ItemizeOptions::script_runs]
+
+mod common;
+
+use common::{chain, runs_with};
+use itemizer::ItemizeOptions;
+
+/// (run text, family name, script) for each run when splitting by script
+fn itemize_scripts(text: &str) -> Vec<(&str, &'static str, String)> {
+    let options = ItemizeOptions {
+        script_runs: true,
+        ..Default::default()
+    };
+    runs_with(text, "", &options)
+        .iter()
+        .map(|r| {
+            (
+                &text[r.start..r.end],
+                r.family.unwrap().family_name.as_str(),
+                r.script.unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn script_runs_split_within_a_family() {
+    assert_eq!(
+        vec![
+            ("abc ", "Roboto", "Latn".to_string()),
+            ("αβγ", "Roboto", "Grek".to_string()),
+        ],
+        itemize_scripts("abc αβγ")
+    );
+}
+
+#[test]
+fn script_runs_pair_brackets() {
+    assert_eq!(
+        vec![
+            ("abc (", "Roboto", "Latn".to_string()),
+            ("αβγ", "Roboto", "Grek".to_string()),
+            (") def", "Roboto", "Latn".to_string()),
+        ],
+        itemize_scripts("abc (αβγ) def")
+    );
+}
+
+#[test]
+fn script_runs_resolve_common() {
+    // Leading digits take the script that follows
+    assert_eq!(
+        vec![("12 abc", "Roboto", "Latn".to_string())],
+        itemize_scripts("12 abc")
+    );
+    assert_eq!(
+        vec![("12, 34", "Roboto", "Zyyy".to_string())],
+        itemize_scripts("12, 34")
+    );
+}
+
+#[test]
+fn script_runs_are_optional() {
+    let mut runs = Vec::new();
+    chain().itemize("abc αβγ", "", &mut runs).unwrap();
+    assert_eq!(1, runs.len());
+    assert_eq!(None, runs[0].script);
+}
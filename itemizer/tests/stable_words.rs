@@ -0,0 +1,49 @@
+//! Keeping each word in one family, see [itemizer::ItemizeOptions::stable_words]
+
+mod common;
+
+use common::itemize_with;
+use itemizer::ItemizeOptions;
+
+#[test]
+fn words_move_to_a_family_that_supports_all_of_them() {
+    // U+1EC7 LATIN SMALL LETTER E WITH CIRCUMFLEX AND DOT BELOW, which Roboto lacks
+    assert_eq!(
+        vec![
+            ("Vi", Some("Roboto")),
+            ("\u{1ec7}", Some("Noto Sans")),
+            ("t Nam", Some("Roboto")),
+        ],
+        itemize_with("Vi\u{1ec7}t Nam", "", &ItemizeOptions::default())
+    );
+    assert_eq!(
+        vec![("Vi\u{1ec7}t", Some("Noto Sans")), (" Nam", Some("Roboto"))],
+        itemize_with(
+            "Vi\u{1ec7}t Nam",
+            "",
+            &ItemizeOptions {
+                stable_words: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn words_no_family_supports_stay_per_grapheme() {
+    assert_eq!(
+        vec![
+            ("Vi", Some("Roboto")),
+            ("\u{1ec7}", Some("Noto Sans")),
+            ("\u{10000}", None),
+        ],
+        itemize_with(
+            "Vi\u{1ec7}\u{10000}",
+            "",
+            &ItemizeOptions {
+                stable_words: true,
+                ..Default::default()
+            }
+        )
+    );
+}
@@ -0,0 +1,89 @@
+//! Keeping spaces, punctuation and digits in the family around them, see
+//! [itemizer::ItemizeOptions::sticky_neutrals]
+
+mod common;
+
+use common::itemize_with;
+use itemizer::ItemizeOptions;
+
+#[test]
+fn neutrals_stick_to_previous_run() {
+    assert_eq!(
+        vec![
+            ("世界", Some("Noto Sans JP")),
+            (" ", Some("Roboto")),
+            ("世界", Some("Noto Sans JP")),
+        ],
+        itemize_with("世界 世界", "ja", &ItemizeOptions::default())
+    );
+    assert_eq!(
+        vec![("世界 世界", Some("Noto Sans JP"))],
+        itemize_with(
+            "世界 世界",
+            "ja",
+            &ItemizeOptions {
+                sticky_neutrals: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn leading_neutrals_stick_to_following_run() {
+    assert_eq!(
+        vec![("12 世界", Some("Noto Sans JP"))],
+        itemize_with(
+            "12 世界",
+            "ja",
+            &ItemizeOptions {
+                sticky_neutrals: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn neutrals_keep_their_presentation() {
+    let text = "abc\u{a9}\u{fe0f}";
+    let expected = vec![
+        ("abc", Some("Roboto")),
+        ("\u{a9}\u{fe0f}", Some("Noto Color Emoji")),
+    ];
+    assert_eq!(expected, itemize_with(text, "", &ItemizeOptions::default()));
+    assert_eq!(
+        expected,
+        itemize_with(
+            text,
+            "",
+            &ItemizeOptions {
+                sticky_neutrals: true,
+                ..Default::default()
+            }
+        )
+    );
+}
+
+#[test]
+fn neutrals_follow_script_extensions() {
+    // U+0964 DEVANAGARI DANDA is shared by Bengali, it goes to Devanagari on its own
+    assert_eq!(
+        vec![
+            ("বাংলা", Some("Noto Sans Bengali UI")),
+            ("\u{0964}", Some("Noto Sans Devanagari UI")),
+        ],
+        itemize_with("বাংলা\u{0964}", "", &ItemizeOptions::default())
+    );
+    assert_eq!(
+        vec![("বাংলা\u{0964}", Some("Noto Sans Bengali UI"))],
+        itemize_with(
+            "বাংলা\u{0964}",
+            "",
+            &ItemizeOptions {
+                sticky_neutrals: true,
+                ..Default::default()
+            }
+        )
+    );
+}